@@ -0,0 +1,67 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use song_guessr_server::game::{gen_questions, GenerateOptions, QuestionType};
+use song_guessr_server::scoring::ScoringRules;
+use song_guessr_server::source::{Snippet, Track};
+
+const NUM_QUESTIONS: usize = 30;
+
+/// A playlist of tracks with random popularities, where albums have 10 tracks
+/// and artists have 5 albums, released over 60 years
+fn playlist(num_tracks: usize) -> Vec<Track> {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0..num_tracks)
+        .map(|i| Track {
+            id: i.to_string(),
+            name: format!("Song {i}"),
+            album: format!("Album {}", i / 10),
+            artists: vec![format!("Artist {}", i / 50)],
+            year: Some(1960 + (i / 10 % 60) as i32),
+            popularity: rng.gen_range(0..=100),
+            isrc: None,
+            snippet: Some(Snippet::Url(format!("https://example.com/{i}.mp3"))),
+        })
+        .collect()
+}
+
+fn bench_gen_questions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gen_questions");
+    for num_tracks in [1_000, 10_000, 100_000] {
+        let tracks = playlist(num_tracks);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_tracks),
+            &tracks,
+            |b, tracks| {
+                let mut rng = StdRng::seed_from_u64(0);
+                b.iter_batched(
+                    || tracks.clone(),
+                    |tracks| {
+                        gen_questions(
+                            &mut rng,
+                            tracks,
+                            NUM_QUESTIONS,
+                            vec![
+                                QuestionType::Song,
+                                QuestionType::Album,
+                                QuestionType::Artist,
+                                QuestionType::Year,
+                                QuestionType::Decade,
+                            ],
+                            &GenerateOptions {
+                                partial_credit: true,
+                                ..GenerateOptions::default()
+                            },
+                            &ScoringRules::default(),
+                        )
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_gen_questions);
+criterion_main!(benches);
@@ -0,0 +1,55 @@
+//! Judging of typed answers to free-text questions
+
+use crate::title;
+
+/// Minimum similarity of a typed answer to the answer to be accepted
+pub const ACCEPT_SIMILARITY: f64 = 0.85;
+/// Minimum similarity of a typed answer to the answer to get part of the score
+pub const CLOSE_SIMILARITY: f64 = 0.7;
+
+/// Normalize a typed answer, or the answer of a question, so that answers differing
+/// only in case, accents, punctuation, or version details compare equal.
+/// Version details are found the same way as in displayed titles, see [`title::clean`].
+pub fn normalize(text: &str) -> String {
+    title::key(text)
+}
+
+/// Similarity between a typed answer and an answer, from 0 (nothing in common)
+/// to 1 (equal after normalization)
+pub fn similarity(guess: &str, answer: &str) -> f64 {
+    let (guess, answer) = (normalize(guess), normalize(answer));
+    if guess.is_empty() || answer.is_empty() {
+        return 0.0;
+    }
+    strsim::normalized_levenshtein(&guess, &answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn similarity_ignores_formatting_and_versions() {
+        assert_eq!(similarity("bohemian rhapsody", "Bohemian Rhapsody"), 1.0);
+        assert_eq!(similarity("Beatles", "The Beatles"), 1.0);
+        assert_eq!(similarity("Song", "Song - 2011 Remaster"), 1.0);
+        assert_eq!(similarity("Song", "Song (feat. Artist)"), 1.0);
+        // parts of the title itself must be typed, as they are shown with the choices
+        assert!(similarity("Song", "Song (Live Forever)") < CLOSE_SIMILARITY);
+    }
+
+    #[test]
+    fn similarity_accepts_typos() {
+        assert!(similarity("bohemian rhapsodie", "Bohemian Rhapsody") >= ACCEPT_SIMILARITY);
+        let close = similarity("bohemian", "Bohemian Rhapsody!");
+        assert!(close < ACCEPT_SIMILARITY);
+        assert!(similarity("Yesterday", "Bohemian Rhapsody") < CLOSE_SIMILARITY);
+    }
+
+    #[test]
+    fn similarity_of_empty_answers() {
+        assert_eq!(similarity("", "Song"), 0.0);
+        assert_eq!(similarity("!!", "Song"), 0.0);
+        assert_eq!(similarity("Song", ""), 0.0);
+    }
+}
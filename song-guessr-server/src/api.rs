@@ -1,29 +1,80 @@
 use dashmap::DashMap;
-use rspotify::model::SimplifiedPlaylist;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 use axum::{
     extract::{
         ws::{Message, WebSocket},
-        Path, Query, State, WebSocketUpgrade,
+        Path, Query, Request, State, WebSocketUpgrade,
     },
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     routing::{get, post, put},
     Json, Router,
 };
 
-use crate::{client, game};
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+use crate::{audio, game, import, pack, resolver, source, store};
 
 struct AppState {
-    client: client::Client,
+    source: Arc<dyn source::MusicSource>,
+    snippets: audio::SnippetCache,
+    resolvers: resolver::ResolverChain,
+    importer: import::Importer,
+    packs: pack::PackStore,
     rooms: DashMap<String, Arc<game::Room>>,
 }
 
-// TODO: properly classify the error
+// TODO: properly classify the remaining errors
 struct AppError(anyhow::Error);
 
+/// An error caused by a request rather than by the server
+#[derive(Debug, thiserror::Error)]
+enum RequestError {
+    /// The request can't be handled as is, e.g. a game without sources
+    #[error("{0}")]
+    Invalid(String),
+    #[error("Only the room owner can {0}")]
+    NotRoomOwner(&'static str),
+    #[error("Room {0} not found")]
+    RoomNotFound(String),
+    /// The room's game isn't in a state allowing the request
+    #[error("{0}")]
+    Conflict(&'static str),
+    /// An uploaded file can't be parsed
+    #[error("{0:#}")]
+    Unparsable(anyhow::Error),
+}
+
+/// Status of an error caused by the request, `None` for errors of the server
+fn request_error_status(err: &anyhow::Error) -> Option<StatusCode> {
+    if let Some(err) = err.downcast_ref::<RequestError>() {
+        return Some(match err {
+            RequestError::Invalid(_) => StatusCode::BAD_REQUEST,
+            RequestError::NotRoomOwner(_) => StatusCode::FORBIDDEN,
+            RequestError::RoomNotFound(_) => StatusCode::NOT_FOUND,
+            RequestError::Conflict(_) => StatusCode::CONFLICT,
+            RequestError::Unparsable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        });
+    }
+    if let Some(pack::PackError::NotUploader { .. }) = err.downcast_ref() {
+        return Some(StatusCode::FORBIDDEN);
+    }
+    if err.is::<store::NotFound>() {
+        return Some(StatusCode::NOT_FOUND);
+    }
+    // the game's settings, sources or quiz pack can't be used to play a game
+    if err.is::<game::GenerateError>()
+        || err.is::<game::SettingsError>()
+        || err.is::<pack::PackError>()
+    {
+        return Some(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    None
+}
+
 impl<E> From<E> for AppError
 where
     E: Into<anyhow::Error>,
@@ -35,12 +86,28 @@ where
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        let (status, retry_after) = match self.0.downcast_ref::<source::SourceError>() {
+            Some(source::SourceError::Unauthorized) => (StatusCode::SERVICE_UNAVAILABLE, None),
+            Some(source::SourceError::RateLimited { retry_after }) => {
+                (StatusCode::TOO_MANY_REQUESTS, Some(retry_after.as_secs()))
+            }
+            None => (
+                request_error_status(&self.0).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                None,
+            ),
+        };
+        let mut response = (
+            status,
             // TODO: the error message should be hidden in production
             format!("Something went wrong: {}", self.0),
         )
-            .into_response()
+            .into_response();
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
     }
 }
 
@@ -59,16 +126,22 @@ enum WsServerMessage {
     Playing {
         question: game::Question,
         question_id: usize,
-        song_progress_ms: u32,
+        // negative while the question's pre-roll is running
+        song_progress_ms: i64,
+        settings: game::GameSettings,
         users: Vec<game::User>,
     },
     WaitingForNextQuestion {
         answer: String,
         correct_submissions: Vec<game::UserSubmission>,
+        // all submissions with their guesses and verdicts
+        submissions: Vec<game::UserSubmission>,
         users: Vec<game::User>,
     },
     Ended {
         users: Vec<game::User>,
+        // seed of the game's generated questions, to replay the game
+        seed: Option<u64>,
     },
 }
 
@@ -117,7 +190,7 @@ async fn get_room_ws(
 
             room.on_user_leave(
                 &user_id,
-                matches!(&*room.game.read(), &game::GameState::Waiting { .. }),
+                matches!(&*room.game.read(), &game::GameState::Waiting),
             );
         })
     } else {
@@ -138,10 +211,16 @@ async fn on_game_state_update(socket: &mut WebSocket, room: &game::Room) -> anyh
             }
             game::GameState::Playing(state) => match state.question_state.status {
                 game::QuestionStatus::Playing => {
+                    let mut question = state.current_question().clone();
+                    // the choices of free-text questions would give the answer away
+                    if question.answer_mode == game::AnswerMode::FreeText {
+                        question.choices.clear();
+                    }
                     let msg = WsServerMessage::Playing {
-                        question: state.current_question().clone(),
+                        question,
                         question_id: state.question_state.id,
-                        song_progress_ms: state.question_state.timer.elapsed().as_millis() as u32,
+                        song_progress_ms: state.song_progress_ms(),
+                        settings: state.settings,
                         users: room.users(),
                     };
                     let data = serde_json::to_string(&msg)?;
@@ -149,24 +228,25 @@ async fn on_game_state_update(socket: &mut WebSocket, room: &game::Room) -> anyh
                 }
                 game::QuestionStatus::Ended => {
                     let current_question = state.current_question();
+                    let submissions = &state.question_state.submissions;
                     let msg = WsServerMessage::WaitingForNextQuestion {
                         answer: current_question.choices[current_question.ans_id].clone(),
-                        correct_submissions: state
-                            .question_state
-                            .submissions
+                        correct_submissions: submissions
                             .iter()
-                            .filter(|s| s.choice == current_question.ans_id)
+                            .filter(|s| s.verdict == Some(game::Verdict::Correct))
                             .cloned()
                             .collect(),
+                        submissions: submissions.clone(),
                         users: room.users(),
                     };
                     let data = serde_json::to_string(&msg)?;
                     Some(Message::Text(data))
                 }
             },
-            &game::GameState::Ended { .. } => {
+            game::GameState::Ended { origin, .. } => {
                 let msg = WsServerMessage::Ended {
                     users: room.users(),
+                    seed: origin.seed(),
                 };
                 let data = serde_json::to_string(&msg)?;
                 Some(Message::Text(data))
@@ -254,28 +334,76 @@ async fn reset_room(
 ) -> Result<Json<()>, AppError> {
     if let Some(room) = state.rooms.get(&id) {
         if room.owner_id != user_id {
-            return Err(anyhow::anyhow!("Only the room owner can reset the room").into());
-        }
-        let mut game = room.game.write();
-        let mut users = room.users.write();
-        users.retain(|u| u.online);
-        for user in users.iter_mut() {
-            user.score = 0;
+            return Err(RequestError::NotRoomOwner("reset the room").into());
         }
-        *game = game::GameState::Waiting;
-        let _ = room.update_broadcast.send(());
+        room.reset();
         Ok(Json(()))
     } else {
-        Err(anyhow::anyhow!("Room {id} not found").into())
+        Err(RequestError::RoomNotFound(id).into())
     }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct NewGameRequest {
     user_id: String,
-    playlist_id: String,
+    sources: Vec<game::GameSource>,
     num_questions: Option<usize>,
     question_types: Vec<game::QuestionType>,
+    // bypass the music source's cache
+    #[serde(default)]
+    force_refresh: bool,
+    // seed of the question generation, to replay a game from the same tracks.
+    // A random seed is used if not given.
+    seed: Option<u64>,
+    #[serde(flatten)]
+    options: game::GenerateOptions,
+    // timing of the game's questions
+    #[serde(default)]
+    settings: game::GameSettings,
+}
+
+#[derive(Debug, Serialize)]
+struct NewGameResponse {
+    // how many of the sources' tracks are usable in the game, not set for quiz pack games
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snippets: Option<resolver::SnippetReport>,
+    // seed of the generated questions, not set for quiz pack games
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+/// Get the merged tracks of a game's sources, along with their snippet report.
+/// Only tracks with a snippet are returned, and only playlists imported in the room are used.
+async fn game_tracks(
+    state: &AppState,
+    room_id: &str,
+    sources: &[game::GameSource],
+    force_refresh: bool,
+) -> anyhow::Result<(Vec<source::Track>, resolver::SnippetReport)> {
+    if sources.is_empty() {
+        return Err(
+            RequestError::Invalid("At least one game source is required".to_string()).into(),
+        );
+    }
+    if let Some(s) = sources
+        .iter()
+        .find(|s| !s.weight.is_finite() || s.weight <= 0.0)
+    {
+        let message = format!("Invalid weight {} of {}", s.weight, s.collection);
+        return Err(RequestError::Invalid(message).into());
+    }
+
+    let tracks = futures::future::try_join_all(sources.iter().map(|s| async move {
+        match &s.collection {
+            source::Collection::Imported { id } => {
+                Ok(state.importer.get(id, room_id).await?.tracks)
+            }
+            collection => state.source.tracks(collection, force_refresh).await,
+        }
+    }))
+    .await?;
+    let tracks = game::merge_source_tracks(sources.iter().map(|s| s.weight).zip(tracks).collect());
+    Ok(state.resolvers.resolve(tracks).await)
 }
 
 async fn new_game(
@@ -283,78 +411,416 @@ async fn new_game(
     State(state): State<Arc<AppState>>,
     Json(NewGameRequest {
         user_id,
-        playlist_id,
+        sources,
         num_questions,
         question_types,
+        force_refresh,
+        seed,
+        options,
+        settings,
     }): Json<NewGameRequest>,
-) -> Result<Json<()>, AppError> {
-    let room = if let Some(room) = state.rooms.get(&id) {
+) -> Result<Json<NewGameResponse>, AppError> {
+    let room = waiting_room(&state, &id, &user_id)?;
+    settings.validate()?;
+
+    let num_questions = num_questions.unwrap_or(15);
+    let seed = seed.unwrap_or_else(game::gen_seed);
+    let (tracks, snippets) = game_tracks(&state, &id, &sources, force_refresh).await?;
+    let questions = game::gen_questions(
+        &mut game::seeded_rng(seed),
+        tracks,
+        num_questions,
+        question_types.clone(),
+        &options,
+        &settings.scoring.rules(),
+    )?;
+
+    room.new_game(
+        game::GameOrigin::Generated {
+            sources,
+            num_questions,
+            question_types,
+            seed,
+            options,
+        },
+        questions,
+        settings,
+    );
+
+    Ok(Json(NewGameResponse {
+        snippets: Some(snippets),
+        seed: Some(seed),
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct NewPackGameRequest {
+    user_id: String,
+    pack_id: String,
+    #[serde(default)]
+    answer_mode: game::AnswerMode,
+    #[serde(default)]
+    settings: game::GameSettings,
+}
+
+async fn new_pack_game(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(NewPackGameRequest {
+        user_id,
+        pack_id,
+        answer_mode,
+        settings,
+    }): Json<NewPackGameRequest>,
+) -> Result<Json<NewGameResponse>, AppError> {
+    let room = waiting_room(&state, &id, &user_id)?;
+    settings.validate()?;
+
+    let questions = pack_questions(&state, &pack_id, answer_mode, &settings).await?;
+    room.new_game(
+        game::GameOrigin::Pack {
+            id: pack_id,
+            answer_mode,
+        },
+        questions,
+        settings,
+    );
+
+    Ok(Json(NewGameResponse {
+        snippets: None,
+        seed: None,
+    }))
+}
+
+/// Get the questions of a stored quiz pack, answered in the given mode and scored
+/// by the game's settings
+async fn pack_questions(
+    state: &AppState,
+    id: &str,
+    answer_mode: game::AnswerMode,
+    settings: &game::GameSettings,
+) -> anyhow::Result<Vec<game::Question>> {
+    let stored = state.packs.get(id).await?;
+    let mut questions = state
+        .packs
+        .questions(&stored.pack, &settings.scoring.rules())?;
+    for question in &mut questions {
+        question.answer_mode = answer_mode;
+    }
+    Ok(questions)
+}
+
+/// Get a room that a game can be started in by the given user
+fn waiting_room(state: &AppState, id: &str, user_id: &str) -> anyhow::Result<Arc<game::Room>> {
+    let room = if let Some(room) = state.rooms.get(id) {
         if !matches!(&*room.game.read(), game::GameState::Waiting) {
-            return Err(anyhow::anyhow!("Game already in progress").into());
+            return Err(RequestError::Conflict("Game already in progress").into());
         }
         room.clone()
     } else {
-        return Err(anyhow::anyhow!("Room {id} not found").into());
+        return Err(RequestError::RoomNotFound(id.to_string()).into());
     };
 
     if room.owner_id != user_id {
-        return Err(anyhow::anyhow!("Only the room owner can start a game").into());
+        return Err(RequestError::NotRoomOwner("start a game").into());
     }
+    Ok(room)
+}
 
-    let num_questions = num_questions.unwrap_or(15);
-    let tracks = state.client.playlist_tracks(&playlist_id).await?;
-    let questions = game::gen_questions(tracks, num_questions, question_types.clone());
-
-    room.new_game(playlist_id, question_types, questions);
-
-    Ok(Json(()))
+/// Check that a room exists and is owned by the given user
+fn check_room_owner(state: &AppState, id: &str, user_id: &str) -> anyhow::Result<()> {
+    match state.rooms.get(id) {
+        Some(room) if room.owner_id == user_id => Ok(()),
+        Some(_) => Err(RequestError::NotRoomOwner("import playlists").into()),
+        None => Err(RequestError::RoomNotFound(id.to_string()).into()),
+    }
 }
 
 async fn restart_game(
     Path(id): Path<String>,
     State(state): State<Arc<AppState>>,
     Json(RoomUpdateRequest { user_id }): Json<RoomUpdateRequest>,
-) -> Result<Json<()>, AppError> {
+) -> Result<Json<NewGameResponse>, AppError> {
     if let Some(room) = state.rooms.get(&id) {
-        let (playlist_id, num_questions, question_types) = if let game::GameState::Ended {
-            playlist_id,
-            num_questions,
-            question_types,
+        let (origin, settings) = if let game::GameState::Ended {
+            origin, settings, ..
         } = &*room.game.read()
         {
-            (playlist_id.clone(), *num_questions, question_types.clone())
+            (origin.clone(), *settings)
         } else {
-            return Err(anyhow::anyhow!("Game has not ended yet").into());
+            return Err(RequestError::Conflict("Game has not ended yet").into());
         };
 
         if room.owner_id != user_id {
-            return Err(anyhow::anyhow!("Only the room owner can restart the game").into());
+            return Err(RequestError::NotRoomOwner("restart the game").into());
         }
 
-        let tracks = state.client.playlist_tracks(&playlist_id).await?;
-        let questions = game::gen_questions(tracks, num_questions, question_types.clone());
-        room.new_game(playlist_id, question_types, questions);
+        let (origin, questions, snippets) = match origin {
+            // a restarted game has new questions, generated with a new seed
+            game::GameOrigin::Generated {
+                sources,
+                num_questions,
+                question_types,
+                seed: _,
+                options,
+            } => {
+                let seed = game::gen_seed();
+                let (tracks, snippets) = game_tracks(&state, &id, &sources, false).await?;
+                let questions = game::gen_questions(
+                    &mut game::seeded_rng(seed),
+                    tracks,
+                    num_questions,
+                    question_types.clone(),
+                    &options,
+                    &settings.scoring.rules(),
+                )?;
+                let origin = game::GameOrigin::Generated {
+                    sources,
+                    num_questions,
+                    question_types,
+                    seed,
+                    options,
+                };
+                (origin, questions, Some(snippets))
+            }
+            game::GameOrigin::Pack { id, answer_mode } => {
+                let questions = pack_questions(&state, &id, answer_mode, &settings).await?;
+                (game::GameOrigin::Pack { id, answer_mode }, questions, None)
+            }
+        };
+        let seed = origin.seed();
+        room.new_game(origin, questions, settings);
 
-        Ok(Json(()))
+        Ok(Json(NewGameResponse { snippets, seed }))
     } else {
-        Err(anyhow::anyhow!("Room {id} not found").into())
+        Err(RequestError::RoomNotFound(id).into())
     }
 }
+
 #[derive(Debug, Deserialize)]
-struct SearchPlaylistParams {
+struct SearchParams {
     query: String,
+    #[serde(default)]
+    kind: source::SearchKind,
+}
+
+async fn search(
+    Query(SearchParams { query, kind }): Query<SearchParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<source::SearchItem>>, AppError> {
+    Ok(state.source.search(&query, kind).await.map(Json)?)
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportPlaylistRequest {
+    user_id: String,
+    // content of the playlist file
+    content: String,
+    name: Option<String>,
+    // format of the playlist file, detected from its content if not given
+    format: Option<import::Format>,
+}
+
+#[derive(Debug, Serialize)]
+struct ImportPlaylistResponse {
+    id: String,
+    name: String,
+    num_tracks: usize,
+    // entries that no music source has a track for
+    unresolved: Vec<source::TrackQuery>,
+}
+
+async fn import_playlist(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(ImportPlaylistRequest {
+        user_id,
+        content,
+        name,
+        format,
+    }): Json<ImportPlaylistRequest>,
+) -> Result<Json<ImportPlaylistResponse>, AppError> {
+    check_room_owner(&state, &id, &user_id)?;
+    let file = import::parse(&content, format).map_err(RequestError::Unparsable)?;
+    let (playlist, unresolved) = state.importer.import(file, name, &id).await?;
+    Ok(Json(ImportPlaylistResponse {
+        id: playlist.id,
+        name: playlist.name,
+        num_tracks: playlist.tracks.len(),
+        unresolved,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct RoomOwnerParams {
+    user_id: String,
+}
+
+async fn list_imported_playlists(
+    Path(id): Path<String>,
+    Query(RoomOwnerParams { user_id }): Query<RoomOwnerParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<import::ImportedPlaylistInfo>>, AppError> {
+    check_room_owner(&state, &id, &user_id)?;
+    Ok(state.importer.list(&id).await.map(Json)?)
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadPackRequest {
+    // content of the quiz pack file
+    content: String,
+    // format of the quiz pack file, detected from its content if not given
+    format: Option<pack::Format>,
+}
+
+#[derive(Debug, Serialize)]
+struct UploadPackResponse {
+    #[serde(flatten)]
+    info: pack::PackInfo,
+    // secret required to delete the pack, only known by the uploader
+    owner_token: String,
+}
+
+async fn upload_pack(
+    State(state): State<Arc<AppState>>,
+    Json(UploadPackRequest { content, format }): Json<UploadPackRequest>,
+) -> Result<Json<UploadPackResponse>, AppError> {
+    let pack = pack::parse(&content, format).map_err(RequestError::Unparsable)?;
+    state.packs.check_audio(&pack).await?;
+    let stored = state.packs.add(pack).await?;
+    Ok(Json(UploadPackResponse {
+        info: pack::PackInfo {
+            id: stored.id,
+            name: stored.pack.name,
+            num_questions: stored.pack.questions.len(),
+        },
+        owner_token: stored.owner_token,
+    }))
+}
+
+async fn list_packs(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<pack::PackInfo>>, AppError> {
+    Ok(state.packs.list().await.map(Json)?)
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportPackParams {
+    #[serde(default)]
+    format: pack::Format,
+}
+
+async fn export_pack(
+    Path(id): Path<String>,
+    Query(ExportPackParams { format }): Query<ExportPackParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, AppError> {
+    let stored = state.packs.get(&id).await?;
+    let content = pack::serialize(&stored.pack, format)?;
+    Ok(attachment(&format!("{id}.{}", format.extension()), content))
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportGameParams {
+    user_id: String,
+    #[serde(default)]
+    format: pack::Format,
 }
 
-async fn search_playlist(
-    Query(SearchPlaylistParams { query }): Query<SearchPlaylistParams>,
+/// Export the questions of the room's current or last game as a quiz pack,
+/// which can be uploaded again to replay the same game
+async fn export_game(
+    Path(id): Path<String>,
+    Query(ExportGameParams { user_id, format }): Query<ExportGameParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, AppError> {
+    let room = if let Some(room) = state.rooms.get(&id) {
+        room.clone()
+    } else {
+        return Err(RequestError::RoomNotFound(id).into());
+    };
+
+    // the exported questions include their answers
+    if room.owner_id != user_id {
+        return Err(RequestError::NotRoomOwner("export the game").into());
+    }
+
+    let questions = match &*room.game.read() {
+        game::GameState::Playing(state) => state.questions.clone(),
+        game::GameState::Ended { questions, .. } => questions.clone(),
+        game::GameState::Waiting => return Err(RequestError::Conflict("No game to export").into()),
+    };
+    let pack = state.packs.pack(format!("Game of room {id}"), &questions)?;
+    let content = pack::serialize(&pack, format)?;
+    Ok(attachment(
+        &format!("game-{id}.{}", format.extension()),
+        content,
+    ))
+}
+
+/// A response that is downloaded as a file with the given name
+fn attachment(file_name: &str, content: String) -> Response {
+    let disposition = format!("attachment; filename=\"{file_name}\"");
+    ([(header::CONTENT_DISPOSITION, disposition)], content).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct DeletePackParams {
+    owner_token: String,
+}
+
+async fn delete_pack(
+    Path(id): Path<String>,
+    Query(DeletePackParams { owner_token }): Query<DeletePackParams>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<SimplifiedPlaylist>>, AppError> {
-    Ok(state.client.search_playlist(query).await.map(Json)?)
+) -> Result<Json<()>, AppError> {
+    state.packs.delete(&id, &owner_token).await?;
+    Ok(Json(()))
+}
+
+async fn get_question_audio(
+    Path((id, question_id)): Path<(String, usize)>,
+    State(state): State<Arc<AppState>>,
+    request: Request,
+) -> Result<Response, AppError> {
+    let room = if let Some(room) = state.rooms.get(&id) {
+        room.clone()
+    } else {
+        return Err(RequestError::RoomNotFound(id).into());
+    };
+
+    // snippets of future questions are not served to prevent players from peeking ahead
+    let snippet = match &*room.game.read() {
+        game::GameState::Playing(state) if question_id <= state.question_state.id => {
+            state.questions[question_id].snippet.clone()
+        }
+        _ => {
+            return Ok((
+                StatusCode::NOT_FOUND,
+                format!("Question {question_id} not found"),
+            )
+                .into_response())
+        }
+    };
+
+    let path = state.snippets.load(state.source.as_ref(), &snippet).await?;
+    // `ServeFile` handles HTTP range requests, which are needed for seeking the audio
+    Ok(ServeFile::new(path).oneshot(request).await?.into_response())
 }
 
-pub fn new_app(client: client::Client) -> Router {
+pub fn new_app(
+    source: Arc<dyn source::MusicSource>,
+    snippets: audio::SnippetCache,
+    resolvers: resolver::ResolverChain,
+    importer: import::Importer,
+    packs: pack::PackStore,
+) -> Router {
     let state = Arc::new(AppState {
-        client,
+        source,
+        snippets,
+        resolvers,
+        importer,
+        packs,
         rooms: DashMap::new(),
     });
 
@@ -363,8 +829,16 @@ pub fn new_app(client: client::Client) -> Router {
         .route("/room/:id", get(get_room_ws))
         .route("/room/:id/is_owner", get(is_room_owner))
         .route("/room/:id/new_game", put(new_game))
+        .route("/room/:id/new_pack_game", put(new_pack_game))
         .route("/room/:id/reset", put(reset_room))
         .route("/room/:id/restart", put(restart_game))
-        .route("/search", get(search_playlist))
+        .route("/room/:id/export", get(export_game))
+        .route("/room/:id/audio/:question_id", get(get_question_audio))
+        .route("/search", get(search))
+        .route("/room/:id/import", post(import_playlist))
+        .route("/room/:id/imports", get(list_imported_playlists))
+        .route("/pack", post(upload_pack))
+        .route("/pack/:id", get(export_pack).delete(delete_pack))
+        .route("/packs", get(list_packs))
         .with_state(state)
 }
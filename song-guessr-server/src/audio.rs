@@ -0,0 +1,392 @@
+use anyhow::Context;
+use dashmap::DashMap;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{redirect, Url};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, SystemTime};
+
+use crate::game;
+use crate::source::{MusicSource, Snippet, SnippetAudio};
+
+// file extensions of cached snippets by MIME type, so that snippets are served with the
+// right type. Snippets of an unknown type are assumed to be MP3 files, e.g. Spotify previews.
+const AUDIO_TYPES: &[(&str, &str)] = &[
+    ("audio/mpeg", "mp3"),
+    ("audio/mp4", "m4a"),
+    ("audio/x-m4a", "m4a"),
+    ("audio/aac", "aac"),
+    ("audio/ogg", "ogg"),
+    ("audio/opus", "opus"),
+    ("audio/flac", "flac"),
+    ("audio/x-flac", "flac"),
+    ("audio/wav", "wav"),
+    ("audio/x-wav", "wav"),
+    ("audio/webm", "webm"),
+];
+const DEFAULT_AUDIO_EXTENSION: &str = "mp3";
+// snippets are short previews, anything larger is not a snippet
+const MAX_SNIPPET_SIZE: usize = 20 * 1024 * 1024;
+const MAX_REDIRECTS: usize = 5;
+// a stalled download fails instead of holding the snippet's download lock, and the
+// players requesting the snippet, indefinitely
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+// client shared by the downloads of remote snippets, so that connections are reused.
+// Snippet URLs may come from anyone uploading a quiz pack, so only public addresses
+// are connected to, to prevent requests to the server's internal network.
+static HTTP: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .dns_resolver(Arc::new(PublicResolver))
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(DOWNLOAD_TIMEOUT)
+        .redirect(redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if let Err(err) = check_url(attempt.url()) {
+                attempt.error(err)
+            } else {
+                attempt.follow()
+            }
+        }))
+        .build()
+        .expect("snippet HTTP client should be valid")
+});
+
+/// A disk cache of remote audio snippets, so that each snippet is downloaded from the
+/// music source at most once while it's cached. Once the cache is larger than its maximum
+/// size, the least recently used snippets are removed.
+pub struct SnippetCache {
+    dir: PathBuf,
+    max_size: u64,
+    // total size of the cached snippets, in bytes
+    size: AtomicU64,
+    // locks of snippets being downloaded, to avoid downloading the same snippet
+    // multiple times when all players request it at the start of a question
+    downloads: DashMap<String, Arc<tokio::sync::Mutex<()>>>,
+    // held while removing snippets, so that the same snippets aren't removed twice
+    eviction: tokio::sync::Mutex<()>,
+}
+
+impl SnippetCache {
+    pub fn new(dir: PathBuf, max_size: u64) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let mut size = 0;
+        for entry in std::fs::read_dir(&dir)? {
+            size += entry?.metadata()?.len();
+        }
+        Ok(Self {
+            dir,
+            max_size,
+            size: AtomicU64::new(size),
+            downloads: DashMap::new(),
+            eviction: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Get the path of a local file containing the snippet's audio,
+    /// downloading the snippet from the music source if needed
+    pub async fn load(
+        &self,
+        source: &dyn MusicSource,
+        snippet: &Snippet,
+    ) -> anyhow::Result<PathBuf> {
+        let url = match snippet {
+            Snippet::File(path) => return Ok(path.clone()),
+            Snippet::Url(url) => url,
+        };
+
+        let name = hash(url);
+        let lock = self.downloads.entry(name.clone()).or_default().clone();
+        let _guard = lock.lock().await;
+        let result = self.download(source, snippet, &name).await;
+        // the lock is removed whether the download succeeds or not, a failed download
+        // is retried by the next request of the snippet
+        self.downloads.remove(&name);
+        result
+    }
+
+    async fn download(
+        &self,
+        source: &dyn MusicSource,
+        snippet: &Snippet,
+        name: &str,
+    ) -> anyhow::Result<PathBuf> {
+        if let Some(path) = self.cached(name).await? {
+            return Ok(path);
+        }
+
+        let SnippetAudio { data, content_type } = source.snippet_audio(snippet).await?;
+        let extension = content_type
+            .as_deref()
+            .and_then(|content_type| {
+                AUDIO_TYPES
+                    .iter()
+                    .find(|(mime, _)| content_type.eq_ignore_ascii_case(mime))
+            })
+            .map_or(DEFAULT_AUDIO_EXTENSION, |(_, extension)| extension);
+        let path = self.dir.join(format!("{name}.{extension}"));
+        // write to a temporary file first so that partially written snippets are never served.
+        // Its name is unique as a snippet may be downloaded again while a failed download's
+        // lock is being removed.
+        let tmp_path = self.dir.join(format!("{name}.{}.tmp", game::gen_id(8)));
+        let len = data.len() as u64;
+        tokio::fs::write(&tmp_path, data).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+
+        if self.size.fetch_add(len, Ordering::SeqCst) + len > self.max_size {
+            // the snippet is already cached, failing to make room for it is only logged
+            if let Err(err) = self.evict(&path).await {
+                eprintln!("Failed to remove snippets from the cache: {err:#}");
+            }
+        }
+        Ok(path)
+    }
+
+    /// Get the path of a cached snippet, whatever its type
+    async fn cached(&self, name: &str) -> anyhow::Result<Option<PathBuf>> {
+        // the default extension is one of the known types' extensions
+        for (_, extension) in AUDIO_TYPES {
+            let path = self.dir.join(format!("{name}.{extension}"));
+            if tokio::fs::try_exists(&path).await? {
+                // the modification time of snippets is their last use, see `evict`
+                let file = tokio::fs::OpenOptions::new()
+                    .write(true)
+                    .open(&path)
+                    .await?;
+                file.into_std().await.set_modified(SystemTime::now())?;
+                return Ok(Some(path));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remove the least recently used snippets until the cache fits in its maximum size,
+    /// keeping the given snippet, which is about to be used
+    async fn evict(&self, keep: &Path) -> anyhow::Result<()> {
+        let _guard = self.eviction.lock().await;
+
+        let mut snippets = Vec::new();
+        let mut size = 0;
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            size += metadata.len();
+            // snippets being written aren't used yet
+            let path = entry.path();
+            if path != keep && path.extension().is_some_and(|ext| ext != "tmp") {
+                snippets.push((metadata.modified()?, metadata.len(), path));
+            }
+        }
+        snippets.sort();
+
+        for (_, len, path) in snippets {
+            if size <= self.max_size {
+                break;
+            }
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => size -= len,
+                // removed since it was listed
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => size -= len,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        // the size is recomputed to also count snippets of failed or concurrent downloads
+        self.size.store(size, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Download the audio of a remote snippet.
+/// Fails if the URL isn't a public HTTP(S) URL or doesn't serve audio.
+pub async fn download(url: &str) -> anyhow::Result<SnippetAudio> {
+    let (mut response, content_type) = request(url).await?;
+    // the content length may be missing or wrong, so the size is also checked while reading
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if data.len() + chunk.len() > MAX_SNIPPET_SIZE {
+            anyhow::bail!("audio of {url} is larger than {MAX_SNIPPET_SIZE} bytes");
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(SnippetAudio { data, content_type })
+}
+
+/// Check that a remote snippet can be downloaded, without downloading its audio
+pub async fn probe(url: &str) -> anyhow::Result<()> {
+    request(url).await?;
+    Ok(())
+}
+
+/// Request the audio of a remote snippet, returning the response and its content type
+/// once the response is known to be audio of a snippet's size
+async fn request(url: &str) -> anyhow::Result<(reqwest::Response, Option<String>)> {
+    let parsed = Url::parse(url).with_context(|| format!("invalid URL {url}"))?;
+    check_url(&parsed)?;
+    let response = HTTP.get(parsed).send().await?.error_for_status()?;
+
+    // parameters such as "; charset=..." are not part of the type
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_lowercase());
+    if !content_type
+        .as_deref()
+        .is_some_and(|content_type| content_type.starts_with("audio/"))
+    {
+        anyhow::bail!(
+            "{url} is not audio, its content type is {}",
+            content_type.as_deref().unwrap_or("unknown")
+        );
+    }
+
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_SNIPPET_SIZE as u64)
+    {
+        anyhow::bail!("audio of {url} is larger than {MAX_SNIPPET_SIZE} bytes");
+    }
+    Ok((response, content_type))
+}
+
+/// Check that a snippet URL is an HTTP(S) URL that doesn't point to a non-public address.
+/// Host names are checked when they are resolved, see `PublicResolver`.
+pub fn check_url(url: &Url) -> anyhow::Result<()> {
+    if !matches!(url.scheme(), "http" | "https") {
+        anyhow::bail!("audio URL {url} is not an HTTP(S) URL");
+    }
+    let Some(host) = url.host_str() else {
+        anyhow::bail!("audio URL {url} has no host");
+    };
+    // IPv6 hosts are enclosed in brackets, e.g. "[::1]"
+    let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse() else {
+        return Ok(());
+    };
+    if !is_public(ip) {
+        anyhow::bail!("audio URL {url} points to a non-public address");
+    }
+    Ok(())
+}
+
+/// A DNS resolver only returning public addresses, so that host names pointing to
+/// the server's internal network, e.g. "localhost", can't be connected to
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether an address is publicly routable, as opposed to e.g. loopback, private,
+/// link-local, or reserved addresses
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "this network", shared address space (carrier-grade NAT), IETF protocol
+        // assignments, benchmarking, and reserved addresses
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // unique local, link-local, and documentation addresses
+        || (segments[0] & 0xfe00) == 0xfc00
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+fn hash(s: &str) -> String {
+    Sha256::digest(s.as_bytes())
+        .iter()
+        .fold(String::new(), |mut acc, b| {
+            let _ = write!(acc, "{b:02x}");
+            acc
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{MemorySource, Track};
+
+    fn snippet(id: usize) -> Snippet {
+        Snippet::Url(format!("https://example.com/{id}.mp3"))
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_snippets_are_evicted() {
+        let source = MemorySource::new(
+            (0..4)
+                .map(|id| Track {
+                    id: id.to_string(),
+                    name: format!("Song {id}"),
+                    album: "Album".to_string(),
+                    artists: vec!["Artist".to_string()],
+                    year: None,
+                    popularity: 50,
+                    isrc: None,
+                    snippet: Some(snippet(id)),
+                })
+                .collect(),
+        );
+        let snippet_size = source.snippet_audio(&snippet(0)).await.unwrap().data.len() as u64;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SnippetCache::new(dir.path().to_path_buf(), 2 * snippet_size).unwrap();
+
+        let mut paths = Vec::new();
+        for id in 0..3 {
+            paths.push(cache.load(&source, &snippet(id)).await.unwrap());
+            // so that the snippets are used at different times
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // the first snippet is removed to make room for the third one
+        assert!(!paths[0].exists() && paths[1].exists() && paths[2].exists());
+        assert_eq!(cache.size.load(Ordering::SeqCst), 2 * snippet_size);
+
+        // using a cached snippet keeps it in the cache
+        cache.load(&source, &snippet(1)).await.unwrap();
+        let last = cache.load(&source, &snippet(3)).await.unwrap();
+        assert!(paths[1].exists() && !paths[2].exists() && last.exists());
+    }
+}
@@ -0,0 +1,69 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::game;
+use crate::source::Track;
+
+/// Tracks of a playlist at a specific snapshot
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedPlaylist {
+    pub snapshot_id: String,
+    pub fetched_at: DateTime<Utc>,
+    pub tracks: Vec<Track>,
+}
+
+impl CachedPlaylist {
+    pub fn new(snapshot_id: String, tracks: Vec<Track>) -> Self {
+        Self {
+            snapshot_id,
+            fetched_at: Utc::now(),
+            tracks,
+        }
+    }
+}
+
+/// A disk cache of playlist tracks, so that repeated games don't re-download the same playlists.
+///
+/// Cached playlists are used as is until their TTL expires, after which they are only
+/// re-downloaded if the playlist's snapshot has changed.
+pub struct PlaylistCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl PlaylistCache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, ttl })
+    }
+
+    /// Get a cached playlist, regardless of whether it has expired
+    pub async fn get(&self, playlist_id: &str) -> Option<CachedPlaylist> {
+        let data = tokio::fs::read(self.path(playlist_id)).await.ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    pub fn is_expired(&self, playlist: &CachedPlaylist) -> bool {
+        (Utc::now() - playlist.fetched_at)
+            .to_std()
+            .is_ok_and(|age| age > self.ttl)
+    }
+
+    pub async fn put(&self, playlist_id: &str, playlist: &CachedPlaylist) -> anyhow::Result<()> {
+        let path = self.path(playlist_id);
+        // write to a temporary file first so that a partially written playlist is never read.
+        // Its name is unique so that concurrent refreshes of a playlist don't write to the same file.
+        let tmp_path = self
+            .dir
+            .join(format!("{playlist_id}.{}.tmp", game::gen_id(8)));
+        tokio::fs::write(&tmp_path, serde_json::to_vec(playlist)?).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
+
+    fn path(&self, playlist_id: &str) -> PathBuf {
+        self.dir.join(format!("{playlist_id}.json"))
+    }
+}
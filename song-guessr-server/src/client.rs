@@ -1,13 +1,47 @@
-use futures::TryStreamExt;
+use anyhow::Context;
+use chrono::Utc;
+use rand::{thread_rng, Rng};
+use reqwest::{header::RETRY_AFTER, StatusCode};
 use rspotify::{
-    model::{FullTrack, PlayableItem, PlaylistId, SearchResult, SearchType, SimplifiedPlaylist},
-    prelude::{BaseClient, OAuthClient},
-    AuthCodePkceSpotify, Config, Credentials, OAuth,
+    http::HttpError,
+    model::{
+        AlbumId, AlbumType, ArtistId, FullAlbum, FullArtist, FullTrack, Page, PlayableItem,
+        PlaylistId, SearchResult, SearchType, SimplifiedAlbum, SimplifiedPlaylist, TimeRange,
+        TrackId,
+    },
+    prelude::{BaseClient, Id, OAuthClient},
+    AuthCodePkceSpotify, ClientCredsSpotify, ClientError, ClientResult, Config, Credentials, OAuth,
+    Token,
 };
 use std::collections::HashSet;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
+use crate::audio;
+use crate::cache::{CachedPlaylist, PlaylistCache};
+use crate::source::{
+    self, Collection, MusicSource, SearchItem, SearchKind, Snippet, SnippetAudio, SourceError,
+    Track, TrackQuery,
+};
+
+// refresh the access token this long before it expires
+const TOKEN_REFRESH_MARGIN: chrono::TimeDelta = chrono::TimeDelta::minutes(5);
+const TOKEN_REFRESH_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+const PAGE_SIZE: u32 = 100;
+const ALBUM_PAGE_SIZE: u32 = 50;
+// maximum page size of the user's library and listening history endpoints
+const USER_PAGE_SIZE: u32 = 50;
+// maximum number of albums requested at once with the "Get Several Albums" endpoint
+const ALBUMS_BATCH_SIZE: usize = 20;
+const MAX_CONCURRENT_REQUESTS: usize = 4;
+const MAX_RETRIES: u32 = 3;
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+// rate limited requests are not retried if Spotify asks to wait longer than this
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(10);
 const REDIRECT_URI: &str = "http://127.0.0.1:8989/login";
-const SPOTIFY_CLIENT_ID: &str = "65b708073fc0480ea92a077233ca87bd";
+pub const SPOTIFY_CLIENT_ID: &str = "65b708073fc0480ea92a077233ca87bd";
 // based on https://github.com/librespot-org/librespot/blob/f96f36c064795011f9fee912291eecb1aa46fff6/src/main.rs#L173
 const OAUTH_SCOPES: &[&str] = &[
     "app-remote-control",
@@ -38,56 +72,679 @@ const OAUTH_SCOPES: &[&str] = &[
     "user-top-read",
 ];
 
+/// How the client obtains Spotify access tokens
+pub enum Auth {
+    /// Authorize as a Spotify user with the PKCE flow.
+    /// The token is loaded from the token cache, or obtained from the given refresh token.
+    User {
+        token_cache: PathBuf,
+        refresh_token: Option<String>,
+    },
+    /// Authorize as the app itself, which only has access to public data such as public playlists
+    ClientCredentials { client_secret: String },
+}
+
 pub struct Client {
     spotify: AuthCodePkceSpotify,
+    // client used to request app tokens in the client credentials mode,
+    // the requested tokens are shared with `spotify`
+    client_creds: Option<ClientCredsSpotify>,
+    // refresh token used when the token cache is missing or unusable
+    refresh_token: Option<String>,
+    // lock to prevent concurrent token refreshes, which would invalidate each other's refresh tokens
+    refresh_lock: tokio::sync::Mutex<()>,
+    // limit of concurrent requests to Spotify
+    requests: tokio::sync::Semaphore,
+    // time until which requests are paused because Spotify is rate limiting the client
+    retry_at: parking_lot::Mutex<Option<tokio::time::Instant>>,
+    playlists: PlaylistCache,
 }
 
 impl Client {
-    pub fn new() -> Self {
+    pub fn new(client_id: &str, auth: Auth, playlists: PlaylistCache) -> Self {
         let oauth = OAuth {
             redirect_uri: REDIRECT_URI.to_string(),
             scopes: HashSet::from_iter(OAUTH_SCOPES.iter().map(|s| s.to_string())),
             ..Default::default()
         };
-        let creds = Credentials::new_pkce(SPOTIFY_CLIENT_ID);
-        let config = Config {
-            token_cached: true,
-            cache_path: std::path::PathBuf::from("/tmp/spotify_token_cache.json"),
-            ..Default::default()
-        };
-        Self {
-            spotify: AuthCodePkceSpotify::with_config(creds, oauth, config),
+
+        match auth {
+            Auth::User {
+                token_cache,
+                refresh_token,
+            } => {
+                let config = Config {
+                    token_cached: true,
+                    cache_path: token_cache,
+                    // tokens are refreshed by the client, see `Client::refresh_token`
+                    token_refreshing: false,
+                    ..Default::default()
+                };
+                Self {
+                    spotify: AuthCodePkceSpotify::with_config(
+                        Credentials::new_pkce(client_id),
+                        oauth,
+                        config,
+                    ),
+                    client_creds: None,
+                    refresh_token,
+                    refresh_lock: tokio::sync::Mutex::new(()),
+                    requests: tokio::sync::Semaphore::new(MAX_CONCURRENT_REQUESTS),
+                    retry_at: parking_lot::Mutex::new(None),
+                    playlists,
+                }
+            }
+            Auth::ClientCredentials { client_secret } => {
+                let config = Config {
+                    token_refreshing: false,
+                    ..Default::default()
+                };
+                let client_creds = ClientCredsSpotify::with_config(
+                    Credentials::new(client_id, &client_secret),
+                    config.clone(),
+                );
+                let mut spotify = AuthCodePkceSpotify::with_config(
+                    Credentials::new_pkce(client_id),
+                    oauth,
+                    config,
+                );
+                spotify.token = client_creds.token.clone();
+                Self {
+                    spotify,
+                    client_creds: Some(client_creds),
+                    refresh_token: None,
+                    refresh_lock: tokio::sync::Mutex::new(()),
+                    requests: tokio::sync::Semaphore::new(MAX_CONCURRENT_REQUESTS),
+                    retry_at: parking_lot::Mutex::new(None),
+                    playlists,
+                }
+            }
+        }
+    }
+
+    /// Obtain an access token without user interaction
+    pub async fn authenticate(&self) -> anyhow::Result<()> {
+        if let Some(client_creds) = &self.client_creds {
+            client_creds
+                .request_token()
+                .await
+                .context("failed to authenticate with client credentials")?;
+            return Ok(());
+        }
+
+        let cached_token = self
+            .spotify
+            .read_token_cache(true)
+            .await
+            .ok()
+            .flatten()
+            .filter(|token| !token.is_expired() || token.refresh_token.is_some());
+        let token = cached_token.or_else(|| {
+            // an expired token that will be refreshed below
+            self.refresh_token.clone().map(|refresh_token| Token {
+                refresh_token: Some(refresh_token),
+                ..Default::default()
+            })
+        });
+        *self.spotify.token.lock().await.unwrap() = token;
+
+        let token = self.spotify.token.lock().await.unwrap().clone();
+        match token {
+            Some(token) if !token.is_expired() => Ok(()),
+            Some(Token {
+                refresh_token: Some(_),
+                ..
+            }) => self
+                .spotify
+                .refresh_token()
+                .await
+                .context("failed to refresh Spotify access token"),
+            _ => anyhow::bail!(
+                "no usable Spotify credentials found, \
+                 run `song-guessr-server auth` to log in or provide a refresh token"
+            ),
         }
     }
 
-    pub async fn get_token(&mut self) -> anyhow::Result<()> {
+    /// Log in interactively through the browser and save the token to the token cache
+    pub async fn login(&mut self) -> anyhow::Result<()> {
+        if self.client_creds.is_some() {
+            anyhow::bail!("interactive login is not needed in the client credentials mode");
+        }
         let url = self.spotify.get_authorize_url(None)?;
         self.spotify.prompt_for_token(&url).await?;
+        self.spotify.write_token_cache().await?;
         Ok(())
     }
 
-    pub async fn search_playlist(&self, query: String) -> anyhow::Result<Vec<SimplifiedPlaylist>> {
-        let result = self
-            .spotify
-            .search(&query, SearchType::Playlist, None, None, None, None)
-            .await?;
+    /// Spawn a background task that refreshes the access token shortly before it expires
+    pub fn spawn_token_refresh(self: Arc<Self>) {
+        tokio::spawn(async move {
+            loop {
+                let (access_token, expires_at) = match &*self.spotify.token.lock().await.unwrap() {
+                    Some(token) => (token.access_token.clone(), token.expires_at),
+                    None => (String::new(), None),
+                };
+                let wait = expires_at.map_or(TOKEN_REFRESH_RETRY_INTERVAL, |expires_at| {
+                    (expires_at - Utc::now() - TOKEN_REFRESH_MARGIN)
+                        .to_std()
+                        .unwrap_or_default()
+                });
+                tokio::time::sleep(wait).await;
+
+                if let Err(err) = self.refresh_token(&access_token).await {
+                    eprintln!("Failed to refresh Spotify access token: {err:#}");
+                    tokio::time::sleep(TOKEN_REFRESH_RETRY_INTERVAL).await;
+                }
+            }
+        });
+    }
+
+    /// Refresh the access token unless it was already refreshed since `stale_access_token` was used.
+    /// Returns `SourceError::Unauthorized` if the credentials are rejected.
+    async fn refresh_token(&self, stale_access_token: &str) -> anyhow::Result<()> {
+        let _guard = self.refresh_lock.lock().await;
+
+        let token = self.spotify.token.lock().await.unwrap().clone();
+        if token
+            .as_ref()
+            .is_some_and(|t| t.access_token != stale_access_token)
+        {
+            return Ok(());
+        }
+
+        let result = if let Some(client_creds) = &self.client_creds {
+            client_creds.request_token().await
+        } else if token.is_some_and(|t| t.refresh_token.is_some()) {
+            self.spotify.refresh_token().await
+        } else {
+            return Err(SourceError::Unauthorized.into());
+        };
+
         match result {
-            SearchResult::Playlists(page) => Ok(page.items),
-            _ => anyhow::bail!("invalid search result"),
+            // the accounts service rejected the credentials, e.g. a revoked refresh token
+            // (`invalid_grant`). Other errors such as rate limits or outages are transient,
+            // and the credentials may still be usable later.
+            Err(err)
+                if matches!(
+                    response_status(&err),
+                    Some(StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED)
+                ) =>
+            {
+                Err(SourceError::Unauthorized.into())
+            }
+            result => Ok(result?),
         }
     }
 
-    pub async fn playlist_tracks(&self, playlist_id: &str) -> anyhow::Result<Vec<FullTrack>> {
+    /// Make a Spotify API call.
+    ///
+    /// The call is retried once with a refreshed access token if the current token is rejected,
+    /// and retried with exponential backoff if Spotify is rate limiting or temporarily unavailable.
+    async fn call<T, F, Fut>(&self, f: F) -> anyhow::Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = ClientResult<T>>,
+    {
+        let mut refreshed = false;
+        let mut attempt = 0;
+        loop {
+            // wait until the rate limit window reported by Spotify is over
+            let retry_at = *self.retry_at.lock();
+            if let Some(retry_at) = retry_at {
+                tokio::time::sleep_until(retry_at).await;
+            }
+
+            let access_token = self.access_token().await;
+            let result = {
+                let _permit = self.requests.acquire().await?;
+                f().await
+            };
+            let err = match result {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+
+            match response_status(&err) {
+                Some(StatusCode::UNAUTHORIZED) if !refreshed => {
+                    self.refresh_token(&access_token).await?;
+                    refreshed = true;
+                }
+                Some(StatusCode::UNAUTHORIZED) => return Err(SourceError::Unauthorized.into()),
+                Some(StatusCode::TOO_MANY_REQUESTS) => {
+                    let retry_after = retry_after(&err).unwrap_or(Duration::from_secs(1));
+                    if attempt >= MAX_RETRIES || retry_after > MAX_RETRY_AFTER {
+                        // other calls still have to wait, even though this one gives up
+                        self.pause_requests(retry_after);
+                        return Err(SourceError::RateLimited { retry_after }.into());
+                    }
+                    let delay = retry_after.max(backoff(attempt));
+                    self.pause_requests(delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Some(status) if status.is_server_error() && attempt < MAX_RETRIES => {
+                    tokio::time::sleep(backoff(attempt)).await;
+                    attempt += 1;
+                }
+                _ => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Pause requests to Spotify for the given time, unless they are already paused for longer
+    fn pause_requests(&self, delay: Duration) {
+        let until = tokio::time::Instant::now() + delay;
+        let mut retry_at = self.retry_at.lock();
+        *retry_at = Some(retry_at.map_or(until, |retry_at| retry_at.max(until)));
+    }
+
+    /// Fetch all items of a paginated endpoint, requesting the pages one by one
+    /// so that every request goes through `Client::call`
+    async fn all_pages<T, F, Fut>(&self, f: F) -> anyhow::Result<Vec<T>>
+    where
+        F: Fn(u32) -> Fut,
+        Fut: Future<Output = ClientResult<Page<T>>>,
+    {
+        let mut items = Vec::new();
+        let mut offset = 0;
+        loop {
+            let page = self.call(|| f(offset)).await?;
+            items.extend(page.items);
+            if page.next.is_none() {
+                break;
+            }
+            offset = page.offset + page.limit;
+        }
+        Ok(items)
+    }
+
+    async fn access_token(&self) -> String {
+        self.spotify
+            .token
+            .lock()
+            .await
+            .unwrap()
+            .as_ref()
+            .map(|t| t.access_token.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get all tracks of a playlist, using the playlist cache unless `force_refresh` is set
+    async fn playlist_tracks(
+        &self,
+        playlist_id: &str,
+        force_refresh: bool,
+    ) -> anyhow::Result<Vec<Track>> {
         let playlist_id = PlaylistId::from_id(playlist_id)?;
-        let stream = self.spotify.playlist_items(playlist_id, None, None);
 
-        let mut tracks: Vec<FullTrack> = Vec::new();
-        futures::pin_mut!(stream);
-        while let Some(item) = stream.try_next().await? {
-            if let Some(PlayableItem::Track(track)) = item.track {
-                tracks.push(track);
+        let cached = if force_refresh {
+            None
+        } else {
+            self.playlists.get(playlist_id.id()).await
+        };
+        if let Some(cached) = &cached {
+            if !self.playlists.is_expired(cached) {
+                return Ok(cached.tracks.clone());
             }
         }
+
+        // the playlist object contains both its snapshot ID and the first page of its tracks
+        let playlist = self
+            .call(|| self.spotify.playlist(playlist_id.as_ref(), None, None))
+            .await?;
+        if let Some(cached) = cached {
+            if cached.snapshot_id == playlist.snapshot_id {
+                let cached = CachedPlaylist::new(cached.snapshot_id, cached.tracks);
+                self.cache_playlist(playlist_id.id(), &cached).await;
+                return Ok(cached.tracks);
+            }
+        }
+
+        // fetch the remaining pages one by one, so that every request goes through `Client::call`
+        let mut tracks: Vec<Track> = Vec::new();
+        let mut page = playlist.tracks;
+        loop {
+            let next_offset = page.offset + page.limit;
+            let has_next = page.next.is_some();
+            tracks.extend(page.items.into_iter().filter_map(|item| match item.track {
+                Some(PlayableItem::Track(track)) => Some(to_track(track)),
+                _ => None,
+            }));
+            if !has_next {
+                break;
+            }
+
+            page = self
+                .call(|| {
+                    self.spotify.playlist_items_manual(
+                        playlist_id.as_ref(),
+                        None,
+                        None,
+                        Some(PAGE_SIZE),
+                        Some(next_offset),
+                    )
+                })
+                .await?;
+        }
+
+        let cached = CachedPlaylist::new(playlist.snapshot_id, tracks);
+        self.cache_playlist(playlist_id.id(), &cached).await;
+        Ok(cached.tracks)
+    }
+
+    /// Cache a playlist's tracks. Failing to cache them doesn't fail the request,
+    /// as the tracks are already fetched.
+    async fn cache_playlist(&self, playlist_id: &str, playlist: &CachedPlaylist) {
+        if let Err(err) = self.playlists.put(playlist_id, playlist).await {
+            eprintln!("Failed to cache playlist {playlist_id}: {err:#}");
+        }
+    }
+
+    /// Get all tracks of an album, fetching the pages that aren't included in the album object
+    async fn album_tracks(&self, album: FullAlbum) -> anyhow::Result<Vec<Track>> {
+        let year = release_year(&album.release_date);
+        let mut tracks = Vec::new();
+        let mut page = album.tracks;
+        loop {
+            let next_offset = page.offset + page.limit;
+            let has_next = page.next.is_some();
+            tracks.extend(page.items.into_iter().map(|track| {
+                Track {
+                    id: track
+                        .id
+                        .map_or_else(|| track.name.clone(), |id| id.id().to_string()),
+                    name: track.name,
+                    album: album.name.clone(),
+                    artists: track.artists.into_iter().map(|a| a.name).collect(),
+                    year,
+                    // simplified tracks don't have popularity data, use the album's instead
+                    popularity: album.popularity,
+                    isrc: None,
+                    snippet: track.preview_url.map(Snippet::Url),
+                }
+            }));
+            if !has_next {
+                break;
+            }
+
+            page = self
+                .call(|| {
+                    self.spotify.album_track_manual(
+                        album.id.as_ref(),
+                        None,
+                        Some(ALBUM_PAGE_SIZE),
+                        Some(next_offset),
+                    )
+                })
+                .await?;
+        }
+        Ok(tracks)
+    }
+
+    /// Get all tracks of an artist's albums and singles
+    async fn artist_tracks(&self, artist_id: &str) -> anyhow::Result<Vec<Track>> {
+        let artist_id = ArtistId::from_id(artist_id)?;
+
+        let album_ids = self
+            .all_pages(|offset| {
+                self.spotify.artist_albums_manual(
+                    artist_id.as_ref(),
+                    [AlbumType::Album, AlbumType::Single],
+                    None,
+                    Some(ALBUM_PAGE_SIZE),
+                    Some(offset),
+                )
+            })
+            .await?
+            .into_iter()
+            .filter_map(|album| album.id)
+            .collect::<Vec<_>>();
+
+        let mut tracks = Vec::new();
+        for ids in album_ids.chunks(ALBUMS_BATCH_SIZE) {
+            let albums = self
+                .call(|| self.spotify.albums(ids.iter().map(|id| id.as_ref()), None))
+                .await?;
+            for album in albums {
+                tracks.extend(self.album_tracks(album).await?);
+            }
+        }
+
+        // singles are usually also released on an album, keep only one of them
+        let mut names = HashSet::new();
+        tracks.retain(|track| names.insert(track.name.to_lowercase()));
         Ok(tracks)
     }
 }
+
+fn response_status(err: &ClientError) -> Option<StatusCode> {
+    match err {
+        ClientError::Http(err) => match &**err {
+            HttpError::StatusCode(response) => Some(response.status()),
+            HttpError::Client(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// Get the delay requested by the `Retry-After` header of a rate limited response
+fn retry_after(err: &ClientError) -> Option<Duration> {
+    match err {
+        ClientError::Http(err) => match &**err {
+            HttpError::StatusCode(response) => response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_secs),
+            HttpError::Client(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// Exponential backoff delay with jitter for the given retry attempt
+fn backoff(attempt: u32) -> Duration {
+    let delay = BACKOFF_BASE * 2u32.pow(attempt);
+    delay + delay.mul_f64(thread_rng().gen_range(0.0..0.5))
+}
+
+#[async_trait::async_trait]
+impl MusicSource for Client {
+    async fn search(&self, query: &str, kind: SearchKind) -> anyhow::Result<Vec<SearchItem>> {
+        let search_type = match kind {
+            SearchKind::Playlist => SearchType::Playlist,
+            SearchKind::Album => SearchType::Album,
+            SearchKind::Artist => SearchType::Artist,
+            SearchKind::Track => SearchType::Track,
+        };
+        let result = self
+            .call(|| {
+                self.spotify
+                    .search(query, search_type, None, None, None, None)
+            })
+            .await?;
+        Ok(match result {
+            SearchResult::Playlists(page) => page.items.into_iter().map(to_playlist).collect(),
+            SearchResult::Albums(page) => page.items.into_iter().filter_map(to_album).collect(),
+            SearchResult::Artists(page) => page.items.into_iter().map(to_artist).collect(),
+            SearchResult::Tracks(page) => page
+                .items
+                .into_iter()
+                .map(|track| {
+                    let track = to_track(track);
+                    SearchItem::Track {
+                        id: track.id,
+                        name: track.name,
+                        album: track.album,
+                        artists: track.artists,
+                    }
+                })
+                .collect(),
+            _ => anyhow::bail!("invalid search result"),
+        })
+    }
+
+    async fn tracks(
+        &self,
+        collection: &Collection,
+        force_refresh: bool,
+    ) -> anyhow::Result<Vec<Track>> {
+        match collection {
+            Collection::Playlist { id } => self.playlist_tracks(id, force_refresh).await,
+            Collection::Album { id } => {
+                let album_id = AlbumId::from_id(id)?;
+                let album = self
+                    .call(|| self.spotify.album(album_id.as_ref(), None))
+                    .await?;
+                self.album_tracks(album).await
+            }
+            Collection::Artist { id } => self.artist_tracks(id).await,
+            Collection::LikedSongs | Collection::TopTracks { .. } | Collection::RecentlyPlayed
+                if self.client_creds.is_some() =>
+            {
+                anyhow::bail!("{collection} are only available when logged in as a Spotify user")
+            }
+            Collection::LikedSongs => {
+                let saved = self
+                    .all_pages(|offset| {
+                        self.spotify.current_user_saved_tracks_manual(
+                            None,
+                            Some(USER_PAGE_SIZE),
+                            Some(offset),
+                        )
+                    })
+                    .await?;
+                Ok(saved.into_iter().map(|s| to_track(s.track)).collect())
+            }
+            Collection::TopTracks { time_range } => {
+                let time_range = match time_range {
+                    source::TimeRange::Short => TimeRange::ShortTerm,
+                    source::TimeRange::Medium => TimeRange::MediumTerm,
+                    source::TimeRange::Long => TimeRange::LongTerm,
+                };
+                let tracks = self
+                    .all_pages(|offset| {
+                        self.spotify.current_user_top_tracks_manual(
+                            Some(time_range),
+                            Some(USER_PAGE_SIZE),
+                            Some(offset),
+                        )
+                    })
+                    .await?;
+                Ok(tracks.into_iter().map(to_track).collect())
+            }
+            Collection::Imported { .. } => {
+                anyhow::bail!("{collection} is not stored in Spotify")
+            }
+            Collection::RecentlyPlayed => {
+                // Spotify only returns the last 50 played tracks
+                let history = self
+                    .call(|| {
+                        self.spotify
+                            .current_user_recently_played(Some(USER_PAGE_SIZE), None)
+                    })
+                    .await?;
+                let mut ids = HashSet::new();
+                Ok(history
+                    .items
+                    .into_iter()
+                    .map(|h| to_track(h.track))
+                    .filter(|t| ids.insert(t.id.clone()))
+                    .collect())
+            }
+        }
+    }
+
+    async fn find_track(&self, query: &TrackQuery) -> anyhow::Result<Option<Track>> {
+        if let Some(id) = query.location.as_deref().and_then(spotify_track_id) {
+            let track_id = TrackId::from_id(id)?;
+            let track = self
+                .call(|| self.spotify.track(track_id.as_ref(), None))
+                .await?;
+            return Ok(Some(to_track(track)));
+        }
+
+        let Some(title) = &query.title else {
+            return Ok(None);
+        };
+        let mut search_query = format!("track:\"{}\"", title.replace('"', ""));
+        if let Some(artist) = &query.artist {
+            search_query += &format!(" artist:\"{}\"", artist.replace('"', ""));
+        }
+        let result = self
+            .call(|| {
+                self.spotify
+                    .search(&search_query, SearchType::Track, None, None, Some(1), None)
+            })
+            .await?;
+        match result {
+            SearchResult::Tracks(page) => Ok(page.items.into_iter().next().map(to_track)),
+            _ => anyhow::bail!("invalid search result"),
+        }
+    }
+
+    async fn snippet_audio(&self, snippet: &Snippet) -> anyhow::Result<SnippetAudio> {
+        match snippet {
+            Snippet::Url(url) => audio::download(url).await,
+            Snippet::File(_) => anyhow::bail!("Spotify client can only load remote snippets"),
+        }
+    }
+}
+
+fn to_playlist(playlist: SimplifiedPlaylist) -> SearchItem {
+    SearchItem::Playlist {
+        id: playlist.id.id().to_string(),
+        name: playlist.name,
+        owner: playlist
+            .owner
+            .display_name
+            .unwrap_or_else(|| playlist.owner.id.id().to_string()),
+    }
+}
+
+fn to_album(album: SimplifiedAlbum) -> Option<SearchItem> {
+    Some(SearchItem::Album {
+        id: album.id?.id().to_string(),
+        name: album.name,
+        artists: album.artists.into_iter().map(|a| a.name).collect(),
+        year: album.release_date.as_deref().and_then(release_year),
+    })
+}
+
+fn to_artist(artist: FullArtist) -> SearchItem {
+    SearchItem::Artist {
+        id: artist.id.id().to_string(),
+        name: artist.name,
+    }
+}
+
+fn to_track(track: FullTrack) -> Track {
+    Track {
+        id: track
+            .id
+            .map_or_else(|| track.name.clone(), |id| id.id().to_string()),
+        name: track.name,
+        album: track.album.name.clone(),
+        artists: track.artists.into_iter().map(|a| a.name).collect(),
+        year: track.album.release_date.as_deref().and_then(release_year),
+        popularity: track.popularity,
+        isrc: track.external_ids.get("isrc").cloned(),
+        snippet: track.preview_url.map(Snippet::Url),
+    }
+}
+
+/// Get the track ID of a Spotify track URI (`spotify:track:<ID>`)
+/// or URL (`https://open.spotify.com/track/<ID>`)
+fn spotify_track_id(location: &str) -> Option<&str> {
+    location.strip_prefix("spotify:track:").or_else(|| {
+        let id = location.strip_prefix("https://open.spotify.com/track/")?;
+        id.split(['?', '/']).next()
+    })
+}
+
+/// Parse the year of a Spotify release date, which is formatted as `YYYY`, `YYYY-MM` or `YYYY-MM-DD`
+fn release_year(date: &str) -> Option<i32> {
+    date.get(..4).and_then(|year| year.parse().ok())
+}
@@ -1,12 +1,38 @@
 use parking_lot::RwLock;
-use rand::{seq::SliceRandom, thread_rng, Rng};
+use rand::{seq::SliceRandom, thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::time::Instant;
 
-const QUESTION_TIMEOUT_SECS: u64 = 10;
-const NEXT_QUESTION_WAIT_TIME_MS: u128 = 1500;
-const SCORE_LIMIT: u64 = 2000;
+use crate::answer;
+use crate::sampler::WeightedSampler;
+use crate::scoring::{ScoringPolicy, ScoringRules, StreakBonus};
+use crate::source::{self, Collection, Snippet};
+use crate::title;
+
+// default timing of questions, see `GameSettings`
+const DEFAULT_ANSWER_TIME_MS: u64 = 10_000;
+const DEFAULT_REVEAL_TIME_MS: u64 = 1500;
+// supported timing of questions, answers can't take longer than a 30 seconds song preview
+const ANSWER_TIME_RANGE_MS: (u64, u64) = (3000, 30_000);
+const REVEAL_TIME_RANGE_MS: (u64, u64) = (500, 30_000);
+const PRE_ROLL_RANGE_MS: (u64, u64) = (0, 10_000);
+// number of choices of a generated question if not given by the game,
+// fewer are used if there aren't enough distinct values
+pub const DEFAULT_NUM_CHOICES: usize = 4;
+pub const MIN_CHOICES: usize = 2;
+pub const MAX_CHOICES: usize = 8;
+// number of questions before the same song is used again as an answer, if possible
+const REPEAT_WINDOW: usize = 10;
+// maximum number of years, or decades, between the choices of year questions and the answer
+// on normal difficulty
+const YEAR_CHOICE_SPREAD: i32 = 5;
+// maximum number of years, or decades, between a choice and the answer to get partial credit
+const PARTIAL_CREDIT_DISTANCE: i32 = 2;
+// random seeds are kept below 2^53 so that they can be represented exactly by JavaScript numbers
+const MAX_RANDOM_SEED: u64 = (1 << 53) - 1;
 
 #[derive(Debug)]
 pub struct Room {
@@ -40,11 +66,16 @@ impl Room {
                 return;
             }
 
+            let question = &state.questions[state.question_state.id];
+            for submission in &mut state.question_state.submissions {
+                submission.verdict = Some(question.verdict(submission));
+            }
+
             let fastest_user = state
                 .question_state
                 .submissions
                 .iter()
-                .filter(|sub| sub.choice == state.questions[state.question_state.id].ans_id)
+                .filter(|sub| sub.verdict == Some(Verdict::Correct))
                 .min_by_key(|sub| sub.submitted_at_ms)
                 .map(|sub| sub.user_id.clone());
 
@@ -55,14 +86,36 @@ impl Room {
                             .as_ref()
                             .map(|id| submission.user_id.eq(id))
                             .unwrap_or(false);
-                        let score = state.questions[state.question_state.id]
-                            .submission_score(submission, is_fastest);
+                        let score = question.submission_score(
+                            submission,
+                            is_fastest,
+                            user.streak,
+                            &state.settings,
+                        );
                         submission.score = Some(score);
-                        user.score += score;
+                        // penalties don't take scores below zero
+                        user.score = user.score.saturating_add_signed(score);
+                        // streaks are only continued by correct answers, close ones end them too
+                        user.streak = if submission.verdict == Some(Verdict::Correct) {
+                            user.streak + 1
+                        } else {
+                            0
+                        };
                         break;
                     }
                 }
             }
+            // users who didn't answer lose their streak
+            for user in users.iter_mut() {
+                if !state
+                    .question_state
+                    .submissions
+                    .iter()
+                    .any(|sub| sub.user_id == user.id)
+                {
+                    user.streak = 0;
+                }
+            }
 
             state.question_state.end_question();
             let _ = self.update_broadcast.send(()); // ignore broadcast send error
@@ -78,9 +131,9 @@ impl Room {
 
             if state.question_state.id == state.questions.len() - 1 {
                 *game = GameState::Ended {
-                    playlist_id: state.playlist_id.clone(),
-                    question_types: state.question_types.clone(),
-                    num_questions: state.questions.len(),
+                    origin: state.origin.clone(),
+                    questions: std::mem::take(&mut state.questions),
+                    settings: state.settings,
                 };
             } else {
                 state.question_state.next_question();
@@ -90,25 +143,33 @@ impl Room {
         }
     }
 
-    pub fn new_game(
-        &self,
-        playlist_id: String,
-        question_types: Vec<QuestionType>,
-        questions: Vec<Question>,
-    ) {
+    /// Go back to waiting for a new game, removing offline users
+    /// and clearing the scores and streaks of the others
+    pub fn reset(&self) {
         let mut game = self.game.write();
-        let mut users = self.users.write();
+        Self::reset_users(&mut self.users.write());
+        *game = GameState::Waiting;
+        let _ = self.update_broadcast.send(());
+    }
 
+    fn reset_users(users: &mut Vec<User>) {
         users.retain(|u| u.online);
         for user in users.iter_mut() {
             user.score = 0;
+            user.streak = 0;
         }
+    }
+
+    pub fn new_game(&self, origin: GameOrigin, questions: Vec<Question>, settings: GameSettings) {
+        let mut game = self.game.write();
+        let mut users = self.users.write();
+        Self::reset_users(&mut users);
 
         *game = GameState::Playing(PlayingGameState {
-            playlist_id,
-            question_types,
+            origin,
             questions,
             question_state: QuestionState::new(),
+            settings,
         });
         let _ = self.update_broadcast.send(());
     }
@@ -117,14 +178,14 @@ impl Room {
         let game = self.game.read();
 
         if let GameState::Playing(state) = &*game {
+            let elapsed_ms = state.question_state.timer.elapsed().as_millis() as u64;
             if state.question_state.status == QuestionStatus::Playing {
                 // end the current question if time is up
-                if state.question_state.timer.elapsed().as_secs() > QUESTION_TIMEOUT_SECS {
+                if elapsed_ms > state.settings.pre_roll_ms + state.settings.answer_time_ms {
                     drop(game);
                     self.on_question_end();
                 }
-            } else if state.question_state.timer.elapsed().as_millis() > NEXT_QUESTION_WAIT_TIME_MS
-            {
+            } else if elapsed_ms > state.settings.reveal_time_ms {
                 // move to the next question if time is up
                 drop(game);
                 self.on_question_next();
@@ -157,16 +218,108 @@ impl Room {
 
 #[derive(Debug)]
 pub struct PlayingGameState {
-    pub playlist_id: String,
-    pub question_types: Vec<QuestionType>,
+    pub origin: GameOrigin,
     pub questions: Vec<Question>,
     pub question_state: QuestionState,
+    pub settings: GameSettings,
 }
 
 impl PlayingGameState {
     pub fn current_question(&self) -> &Question {
         &self.questions[self.question_state.id]
     }
+
+    /// Time since the current question's song started playing, negative during the pre-roll
+    pub fn song_progress_ms(&self) -> i64 {
+        self.question_state.timer.elapsed().as_millis() as i64 - self.settings.pre_roll_ms as i64
+    }
+}
+
+/// Timing and scoring of the questions of a game
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GameSettings {
+    // time to answer a question once its song starts playing
+    pub answer_time_ms: u64,
+    // time that the answer of a question is shown before the next question
+    pub reveal_time_ms: u64,
+    // time between the start of a question and its song, to get ready
+    pub pre_roll_ms: u64,
+    pub scoring: ScoringPolicy,
+    // percentage of a question's score lost by wrong answers, unanswered questions lose nothing
+    pub wrong_answer_penalty_percent: u64,
+    pub streak_bonus: StreakBonus,
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            answer_time_ms: DEFAULT_ANSWER_TIME_MS,
+            reveal_time_ms: DEFAULT_REVEAL_TIME_MS,
+            pre_roll_ms: 0,
+            scoring: ScoringPolicy::default(),
+            wrong_answer_penalty_percent: 0,
+            streak_bonus: StreakBonus::default(),
+        }
+    }
+}
+
+impl GameSettings {
+    /// Check that the settings are within the supported ranges
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        check_range(
+            "answer time",
+            self.answer_time_ms,
+            ANSWER_TIME_RANGE_MS,
+            " ms",
+        )?;
+        check_range(
+            "reveal time",
+            self.reveal_time_ms,
+            REVEAL_TIME_RANGE_MS,
+            " ms",
+        )?;
+        check_range("pre-roll", self.pre_roll_ms, PRE_ROLL_RANGE_MS, " ms")?;
+        check_range(
+            "wrong answer penalty",
+            self.wrong_answer_penalty_percent,
+            (0, 100),
+            "%",
+        )?;
+        self.scoring.validate()?;
+        self.streak_bonus.validate()
+    }
+}
+
+/// A game setting outside of its supported range
+#[derive(Debug, thiserror::Error)]
+#[error("{name} must be between {min}{unit} and {max}{unit}, not {value}{unit}")]
+pub struct SettingsError {
+    name: &'static str,
+    value: u64,
+    min: u64,
+    max: u64,
+    unit: &'static str,
+}
+
+/// Check that a game setting is within the given inclusive range
+pub(crate) fn check_range(
+    name: &'static str,
+    value: u64,
+    (min, max): (u64, u64),
+    unit: &'static str,
+) -> Result<(), SettingsError> {
+    if (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(SettingsError {
+            name,
+            value,
+            min,
+            max,
+            unit,
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -174,10 +327,74 @@ pub enum GameState {
     Waiting,
     Playing(PlayingGameState),
     Ended {
-        playlist_id: String,
+        origin: GameOrigin,
+        // questions of the ended game, kept so that the game can be exported
+        questions: Vec<Question>,
+        // settings of the ended game, kept so that the game can be restarted
+        settings: GameSettings,
+    },
+}
+
+/// Where the questions of a game come from, used to restart the game
+#[derive(Debug, Clone)]
+pub enum GameOrigin {
+    /// Questions generated from the tracks of game sources
+    Generated {
+        sources: Vec<GameSource>,
         num_questions: usize,
         question_types: Vec<QuestionType>,
+        // seed of the RNG used to generate the questions, see `gen_questions`
+        seed: u64,
+        options: GenerateOptions,
     },
+    /// The fixed questions of a quiz pack
+    Pack { id: String, answer_mode: AnswerMode },
+}
+
+impl GameOrigin {
+    /// Seed of the game's generated questions
+    pub fn seed(&self) -> Option<u64> {
+        match self {
+            Self::Generated { seed, .. } => Some(*seed),
+            Self::Pack { .. } => None,
+        }
+    }
+}
+
+/// Options of the questions generated for a game
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GenerateOptions {
+    // number of choices of each question, see `gen_questions`
+    pub num_choices: Option<usize>,
+    // give part of the score to near misses of year and decade questions
+    pub partial_credit: bool,
+    pub answer_mode: AnswerMode,
+    pub difficulty: Difficulty,
+}
+
+/// How similar the distractors of generated questions are to the answer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    /// Distractors unlike the answer, e.g. songs of other artists or distant years
+    Easy,
+    /// Distractors picked regardless of the answer
+    #[default]
+    Normal,
+    /// Distractors like the answer, e.g. songs of the same artist or close years
+    Hard,
+}
+
+/// How players answer the questions of a game
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnswerMode {
+    /// Pick one of the question's choices
+    #[default]
+    MultipleChoice,
+    /// Type the answer, which is accepted if it's close enough to the answer
+    FreeText,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -195,7 +412,7 @@ pub struct QuestionState {
 }
 
 impl QuestionState {
-    pub fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             id: 0,
             submissions: Vec::new(),
@@ -223,6 +440,8 @@ pub struct User {
     pub id: String,
     pub name: String,
     pub score: u64,
+    // number of consecutive correct answers in the current game
+    pub streak: u32,
     pub online: bool,
 }
 
@@ -232,6 +451,7 @@ impl User {
             id,
             name,
             score: 0,
+            streak: 0,
             online: true,
         }
     }
@@ -241,188 +461,780 @@ impl User {
 pub struct UserSubmission {
     pub user_name: String,
     pub user_id: String,
-    pub choice: usize,
-    pub score: Option<u64>,
+    // index of the picked choice, for multiple choice questions
+    #[serde(default)]
+    pub choice: Option<usize>,
+    // typed answer, for free-text questions
+    #[serde(default)]
+    pub text: Option<String>,
+    // negative for wrong answers if the game penalizes them
+    pub score: Option<i64>,
+    // how the submission is judged, set when the question ends
+    #[serde(default)]
+    pub verdict: Option<Verdict>,
     // user submission timestamp in ms w.r.t the start of the question
     pub submitted_at_ms: u32,
 }
 
+/// How a submission is judged against the answer of a question
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// The answer, which gets the full score
+    Correct,
+    /// Close to the answer, which gets part of the score
+    Close,
+    Wrong,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Question {
     pub question_type: QuestionType,
     pub choices: Vec<String>,
-    pub song_url: String,
+    // snippets are served by the server, see `api::get_question_audio`
+    #[serde(skip_serializing)]
+    pub snippet: Snippet,
     pub score: u64,
     pub bonus: u64,
     #[serde(skip)]
     pub ans_id: usize,
+    // whether choices close to the answer get part of the score, for year and decade questions
+    #[serde(default)]
+    pub partial_credit: bool,
+    #[serde(default)]
+    pub answer_mode: AnswerMode,
+    // artists of the answer of artist questions, any of which can be typed as the answer
+    #[serde(skip)]
+    pub answer_artists: Vec<String>,
 }
 
 impl Question {
-    pub fn submission_score(&self, sub: &UserSubmission, is_fastest: bool) -> u64 {
-        if sub.choice == self.ans_id {
-            // the score is reduced linearly based on the time taken to submit
-            // and is reduced closer to (score / 2) if the user submits near the timeout
-            self.score
-                - ((self.score / 2) * (sub.submitted_at_ms as u64) / 1000 / QUESTION_TIMEOUT_SECS)
-                + if is_fastest { self.bonus } else { 0 }
-        } else {
-            0
+    /// Score of a submission by a user with the given streak of correct answers
+    pub fn submission_score(
+        &self,
+        sub: &UserSubmission,
+        is_fastest: bool,
+        streak: u32,
+        settings: &GameSettings,
+    ) -> i64 {
+        // the score is reduced linearly based on the time taken to submit
+        let score = settings.scoring.rules().timed_score(
+            self.score,
+            sub.submitted_at_ms as u64,
+            settings.answer_time_ms,
+        );
+        let score = match self.credit(sub) {
+            Some(0) => {
+                let score = score + if is_fastest { self.bonus } else { 0 };
+                settings.streak_bonus.apply(score, streak)
+            }
+            Some(halvings) => score >> halvings,
+            None => return -((self.score * settings.wrong_answer_penalty_percent / 100) as i64),
+        };
+        score as i64
+    }
+
+    pub fn verdict(&self, sub: &UserSubmission) -> Verdict {
+        match self.credit(sub) {
+            Some(0) => Verdict::Correct,
+            Some(_) => Verdict::Close,
+            None => Verdict::Wrong,
+        }
+    }
+
+    /// Judge a submission, returning how many times its score is halved
+    /// (0 for the answer), or `None` if it gets no score
+    fn credit(&self, sub: &UserSubmission) -> Option<u32> {
+        let answer = &self.choices[self.ans_id];
+        // near misses get half of the score for each year, or decade, away from the answer
+        let distance_credit = |distance: i32| match distance {
+            0 => Some(0),
+            d if self.partial_credit && d <= PARTIAL_CREDIT_DISTANCE => Some(d as u32),
+            _ => None,
+        };
+
+        match self.answer_mode {
+            AnswerMode::MultipleChoice => {
+                let choice = sub.choice?;
+                if choice == self.ans_id {
+                    Some(0)
+                } else {
+                    distance_credit(self.choice_distance(choice)?)
+                }
+            }
+            AnswerMode::FreeText if self.question_type.is_numeric() => {
+                let guess = self.question_type.parse_guess(sub.text.as_deref()?)?;
+                distance_credit((guess - self.question_type.parse_choice(answer)?).abs())
+            }
+            AnswerMode::FreeText => {
+                let guess = sub.text.as_deref()?;
+                // naming any one of a song's artists is enough
+                let answers = std::iter::once(answer.as_str())
+                    .chain(self.answer_artists.iter().map(String::as_str));
+                let similarity = answers
+                    .map(|answer| answer::similarity(guess, answer))
+                    .fold(0.0, f64::max);
+                if similarity >= answer::ACCEPT_SIMILARITY {
+                    Some(0)
+                } else if similarity >= answer::CLOSE_SIMILARITY {
+                    Some(1)
+                } else {
+                    None
+                }
+            }
         }
     }
+
+    /// Number of years, or decades, between a choice of a year or decade question and the answer
+    pub fn choice_distance(&self, choice: usize) -> Option<i32> {
+        let value = |id: usize| {
+            self.choices
+                .get(id)
+                .and_then(|c| self.question_type.parse_choice(c))
+        };
+        Some((value(choice)? - value(self.ans_id)?).abs())
+    }
 }
 
-struct Choice<'a> {
-    value: &'a str,
-    preview_url: &'a str,
-    index: usize,
+/// A collection of tracks used to generate a game
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GameSource {
+    #[serde(flatten)]
+    pub collection: Collection,
+    // how likely the source's tracks are picked relative to the other sources' tracks
+    #[serde(default = "default_source_weight")]
+    pub weight: f64,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+fn default_source_weight() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum QuestionType {
     Song,
     Album,
     Artist,
+    /// Release year of the song's album, e.g. "1994"
+    Year,
+    /// Release decade of the song's album, e.g. "1990s"
+    Decade,
+}
+
+impl fmt::Display for QuestionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Song => write!(f, "song"),
+            Self::Album => write!(f, "album"),
+            Self::Artist => write!(f, "artist"),
+            Self::Year => write!(f, "year"),
+            Self::Decade => write!(f, "decade"),
+        }
+    }
 }
 
 impl QuestionType {
-    /// Generate a question choice based on the type
-    fn gen_choice(self, track: &Track) -> &str {
+    /// Generate a question choice from a track based on the type,
+    /// `None` if the track has no value for the type
+    fn track_value(self, track: &Track) -> Option<String> {
+        match self {
+            Self::Song => Some(title::clean(&track.name)),
+            Self::Album => Some(title::clean(&track.album)),
+            Self::Artist => Some(track.artists.clone()),
+            Self::Year | Self::Decade => track.year.map(|year| self.format_year(year)),
+        }
+    }
+
+    /// Group of tracks whose choices are similar to each other, used to pick distractors
+    /// by difficulty: songs and albums of the same main artist, or artists of the same decade
+    fn similarity_group(self, track: &Track) -> Option<String> {
         match self {
-            Self::Song => &track.name,
-            Self::Album => &track.album,
-            Self::Artist => &track.artists,
+            Self::Song | Self::Album => Some(title::fold(&track.main_artist)),
+            Self::Artist => track.year.map(|year| year.div_euclid(10).to_string()),
+            Self::Year | Self::Decade => None,
         }
     }
+
+    /// Key of a track's choice shared by the versions of the same work,
+    /// so that they aren't shown as different choices
+    fn track_key(self, track: &Track) -> Option<String> {
+        match self {
+            Self::Song => Some(title::key(&track.name)),
+            Self::Album => Some(title::key(&track.album)),
+            Self::Artist => Some(title::fold(&track.artists)),
+            Self::Year | Self::Decade => self.track_value(track),
+        }
+    }
+
+    /// Whether choices of the type are years, generated around the answer's year
+    /// instead of being picked from other tracks
+    fn is_numeric(self) -> bool {
+        matches!(self, Self::Year | Self::Decade)
+    }
+
+    fn format_year(self, year: i32) -> String {
+        match self {
+            Self::Decade => format!("{}s", year.div_euclid(10) * 10),
+            _ => year.to_string(),
+        }
+    }
+
+    /// Parse a choice of a year or decade question into a number of years or decades,
+    /// used to measure how close choices are to each other
+    fn parse_choice(self, choice: &str) -> Option<i32> {
+        match self {
+            Self::Year => choice.trim().parse().ok(),
+            Self::Decade => choice
+                .trim()
+                .strip_suffix('s')?
+                .parse::<i32>()
+                .ok()
+                .map(|year| year.div_euclid(10)),
+            Self::Song | Self::Album | Self::Artist => None,
+        }
+    }
+
+    /// Parse a typed answer of a year or decade question like a choice,
+    /// also accepting decades typed without the "s", e.g. "1990" or "1990's"
+    fn parse_guess(self, guess: &str) -> Option<i32> {
+        let guess = guess.trim().replace('\'', "");
+        match self {
+            Self::Decade if !guess.ends_with('s') => self.parse_choice(&format!("{guess}s")),
+            _ => self.parse_choice(&guess),
+        }
+    }
+
+    /// Generate the choices of a year or decade question whose answer is the given year,
+    /// returning the choices and the index of the answer
+    fn gen_year_choices(
+        self,
+        rng: &mut impl Rng,
+        year: i32,
+        max_year: i32,
+        num_choices: usize,
+        difficulty: Difficulty,
+    ) -> (Vec<String>, usize) {
+        // choices are years or decades, depending on the type
+        let unit = |year: i32| match self {
+            Self::Decade => year.div_euclid(10),
+            _ => year,
+        };
+        let format = |value: i32| match self {
+            Self::Decade => self.format_year(value * 10),
+            _ => self.format_year(value),
+        };
+        let answer = unit(year);
+        // plausible choices at most `spread` away from the answer, which aren't after
+        // the latest release. Spreads are wide enough to have enough candidates wherever
+        // the answer is in the window of candidates.
+        let num_choices = num_choices as i32;
+        let (min_distance, spread) = match difficulty {
+            Difficulty::Hard => (1, num_choices - 1),
+            Difficulty::Normal => (1, YEAR_CHOICE_SPREAD.max(num_choices - 1)),
+            // easy choices are too far from the answer to get partial credit
+            Difficulty::Easy => (
+                PARTIAL_CREDIT_DISTANCE + 1,
+                (2 * YEAR_CHOICE_SPREAD).max(num_choices + 2 * PARTIAL_CREDIT_DISTANCE),
+            ),
+        };
+        // the window of candidates is placed at random around the answer, so the answer
+        // can be anywhere among the choices, and shifted down rather than truncated
+        // when it ends after the latest release
+        let width = spread + 1;
+        let start = answer - rng.gen_range(0..width);
+        let start = start - (start + width - 1 - unit(max_year)).max(0);
+        let candidates = (start..start + width)
+            .filter(|&value| (value - answer).abs() >= min_distance)
+            .collect::<Vec<_>>();
+
+        let mut values = candidates
+            .choose_multiple(rng, num_choices as usize - 1)
+            .copied()
+            .collect::<Vec<_>>();
+        values.push(answer);
+        values.shuffle(rng);
+        let ans_id = values
+            .iter()
+            .position(|&value| value == answer)
+            .unwrap_or_default();
+        let choices = values.into_iter().map(format).collect();
+        (choices, ans_id)
+    }
 }
 
+// names of tracks are only normalized once the tracks are sampled,
+// which is much cheaper than normalizing all tracks for large sources
 struct Track {
     name: String,
     album: String,
+    // names of the track's artists joined by ", ", used as choices
     artists: String,
-    preview_url: String,
-    weight: i32,
+    artist_names: Vec<String>,
+    main_artist: String,
+    // release year of the track's album
+    year: Option<i32>,
+    snippet: Snippet,
+    // how likely the track is picked as a choice, initially based on its popularity
+    weight: u64,
+}
+
+impl Track {
+    /// Key shared by the versions of the track's song
+    fn song_key(&self) -> String {
+        format!(
+            "{}\u{0}{}",
+            title::key(&self.name),
+            title::fold(&self.artists)
+        )
+    }
+}
+
+/// Merge the tracks of multiple game sources, scaling each track's popularity by its source's weight.
+/// Tracks found in several sources are only kept once, with their highest weighted popularity.
+pub fn merge_source_tracks(sources: Vec<(f64, Vec<source::Track>)>) -> Vec<source::Track> {
+    let mut tracks: Vec<source::Track> = Vec::new();
+    // indices of merged tracks by their name and artists, which also catches
+    // the same song released on different albums or in different versions
+    let mut track_ids: HashMap<_, usize> = HashMap::new();
+
+    for (weight, source_tracks) in sources {
+        for mut track in source_tracks {
+            // tracks are picked by their popularity plus one, so that's what is weighted,
+            // otherwise tracks without popularity data wouldn't be affected by the weight
+            let weighted = ((f64::from(track.popularity) + 1.0) * weight).round();
+            track.popularity = weighted.max(1.0) as u32 - 1;
+            let key = (
+                title::key(&track.name),
+                title::fold(&track.artists.join(", ")),
+            );
+            match track_ids.get(&key) {
+                Some(&index) => {
+                    let merged = &mut tracks[index];
+                    merged.popularity = merged.popularity.max(track.popularity);
+                }
+                None => {
+                    track_ids.insert(key, tracks.len());
+                    tracks.push(track);
+                }
+            }
+        }
+    }
+
+    tracks
 }
 
+/// Generate a random seed for the RNG of `gen_questions`
+pub fn gen_seed() -> u64 {
+    thread_rng().gen_range(0..=MAX_RANDOM_SEED)
+}
+
+/// The RNG of `gen_questions` for a game's seed. Unlike `StdRng`, its output is the same
+/// across versions of `rand`, so that a seed keeps generating the same questions.
+pub fn seeded_rng(seed: u64) -> ChaCha8Rng {
+    ChaCha8Rng::seed_from_u64(seed)
+}
+
+/// Why questions can't be generated for a game
+#[derive(Debug, thiserror::Error)]
+pub enum GenerateError {
+    #[error("at least one question is required")]
+    NoQuestions,
+    #[error("at least one question type is required")]
+    NoQuestionTypes,
+    #[error(
+        "the number of choices must be between {MIN_CHOICES} and {MAX_CHOICES}, not {num_choices}"
+    )]
+    InvalidNumChoices { num_choices: usize },
+    #[error("only {found} playable songs are found, at least {required} are required")]
+    NotEnoughTracks { found: usize, required: usize },
+    #[error(
+        "only {found} distinct {question_type} choices are found, at least {required} are required"
+    )]
+    NotEnoughChoices {
+        question_type: QuestionType,
+        found: usize,
+        required: usize,
+    },
+    #[error("{question_type} questions require songs with a release year, but none has one")]
+    NoReleaseYears { question_type: QuestionType },
+}
+
+/// Generate the questions of a game from the given tracks.
+/// The same tracks, in the same order, and an RNG in the same state always generate
+/// the same questions.
+///
+/// Questions have `options.num_choices` choices, and every question type must have that many
+/// distinct values among the tracks. Without `num_choices`, question types without enough
+/// distinct values are skipped, and questions have fewer choices than the default if needed.
+/// With `partial_credit`, year and decade questions award part of the score to near misses.
+/// Questions are scored by the given rules.
 pub fn gen_questions(
-    seed_tracks: Vec<rspotify::model::FullTrack>,
+    rng: &mut impl Rng,
+    seed_tracks: Vec<source::Track>,
     num_questions: usize,
     mut question_types: Vec<QuestionType>,
-) -> Vec<Question> {
-    let mut rng = thread_rng();
+    options: &GenerateOptions,
+    rules: &ScoringRules,
+) -> Result<Vec<Question>, GenerateError> {
+    let GenerateOptions {
+        num_choices,
+        partial_credit,
+        answer_mode,
+        difficulty,
+    } = *options;
+    if num_questions == 0 {
+        return Err(GenerateError::NoQuestions);
+    }
+    if question_types.is_empty() {
+        return Err(GenerateError::NoQuestionTypes);
+    }
+    if let Some(num_choices) = num_choices {
+        if !(MIN_CHOICES..=MAX_CHOICES).contains(&num_choices) {
+            return Err(GenerateError::InvalidNumChoices { num_choices });
+        }
+    }
+    // types are never skipped if the number of choices is chosen by the game
+    let skip_types = num_choices.is_none();
+    // minimum number of distinct values of a question type
+    let required = num_choices.unwrap_or(MIN_CHOICES);
+    let num_choices = num_choices.unwrap_or(DEFAULT_NUM_CHOICES);
 
     let mut tracks = Vec::new();
-    let mut seen_urls = HashSet::new();
+    let mut seen_snippets = HashSet::new();
 
     // process seed tracks
     for track in seed_tracks {
-        let preview_url = if let Some(url) = track.preview_url {
-            url
+        let snippet = if let Some(snippet) = track.snippet {
+            snippet
         } else {
             continue;
         };
 
-        if seen_urls.contains(&preview_url) {
+        if seen_snippets.contains(&snippet) {
             continue;
         }
-        seen_urls.insert(preview_url.clone());
+        seen_snippets.insert(snippet.clone());
 
         tracks.push(Track {
             name: track.name,
-            artists: track
-                .artists
-                .into_iter()
-                .map(|a| a.name)
-                .fold(String::new(), |acc, a| {
-                    if acc.is_empty() {
-                        a
-                    } else {
-                        acc + ", " + &a
-                    }
-                }),
-            album: track.album.name,
-            preview_url,
-            weight: track.popularity as i32,
+            artists: track.artists.join(", "),
+            main_artist: track.artists.first().cloned().unwrap_or_default(),
+            artist_names: track.artists,
+            album: track.album,
+            year: track.year,
+            snippet,
+            // tracks without popularity data can still be picked
+            weight: u64::from(track.popularity) + 1,
         });
     }
 
-    let mut score: u64 = 500;
+    if tracks.len() < required {
+        return Err(GenerateError::NotEnoughTracks {
+            found: tracks.len(),
+            required,
+        });
+    }
+
+    // skip question types that can't have enough choices,
+    // failing with the first type's problem if no type is left
+    let mut first_error = None;
+    question_types.retain(
+        |&question_type| match check_choices(&tracks, question_type, required) {
+            Ok(()) => true,
+            Err(err) => {
+                first_error.get_or_insert(err);
+                false
+            }
+        },
+    );
+    if let Some(err) = first_error {
+        if question_types.is_empty() || !skip_types {
+            return Err(err);
+        }
+    }
+
+    // choices of year and decade questions are at most the latest release year
+    let max_year = tracks
+        .iter()
+        .filter_map(|t| t.year)
+        .max()
+        .unwrap_or_default();
+    let mut sampler = WeightedSampler::new(tracks.iter().map(|t| t.weight).collect());
     let mut questions: Vec<Question> = Vec::new();
-    let mut seen_urls = HashMap::new();
+    // index of the last question that each song, in any version, was the answer of
+    let mut seen_songs = HashMap::new();
+    // tracks by their similarity group of each question type, see `pick_distractors`
+    let mut groups = HashMap::new();
 
     for i in 0..num_questions {
-        let mut choices = Vec::<Choice>::new();
-
         // randomly pick a type for current question
-        question_types.shuffle(&mut rng);
+        question_types.shuffle(rng);
         let question_type = question_types[0];
 
-        // pick question choices from the seed tracks based on the track's weight
-        // and the current question type
-        tracks.sort_by_key(|t| -t.weight);
-        for _ in 0..4 {
-            for (index, track) in tracks.iter().enumerate() {
-                let choice = question_type.gen_choice(track);
-                if choices.iter().any(|c| c.value == choice) {
-                    continue;
-                }
-                choices.push(Choice {
-                    value: choice,
-                    preview_url: &track.preview_url,
-                    index,
-                });
-                break;
-            }
-        }
+        let mut picked = Picked::new(&tracks, question_type);
+        sample_tracks(rng, &mut sampler, &mut picked, num_choices, |_| true);
+        let mut picked = picked.choices;
+        picked.shuffle(rng);
 
-        choices.shuffle(&mut rng);
+        // pick the answer from the sampled tracks, avoiding songs used in recent questions.
+        // If all of them were used recently, the one used the longest time ago is the answer.
+        let since_answer = |index: usize| {
+            seen_songs
+                .get(&tracks[index].song_key())
+                .map(|&j: &usize| i - j)
+        };
+        let fresh = (0..picked.len())
+            .filter(|&j| since_answer(picked[j].1).is_none_or(|n| n >= REPEAT_WINDOW))
+            .collect::<Vec<_>>();
+        let mut answer_pos = match fresh.choose(rng) {
+            Some(&j) => j,
+            None => (0..picked.len())
+                .max_by_key(|&j| since_answer(picked[j].1))
+                .unwrap_or_default(),
+        };
+        let answer = picked[answer_pos].1;
 
-        // generate the answer from the choices
-        let mut ans_id = rng.gen_range(0..4);
-        // ensure that the same song is not repeated within 10 questions
-        while seen_urls
-            .get(choices[ans_id].preview_url)
-            .map(|j| (i - j) < 10)
-            .unwrap_or(false)
-        {
-            ans_id = rng.gen_range(0..4);
+        // pick other distractors for the answer based on the difficulty
+        if difficulty != Difficulty::Normal && !question_type.is_numeric() {
+            let mut choices = Picked::new(&tracks, question_type);
+            choices.add(answer);
+            let groups = groups
+                .entry(question_type)
+                .or_insert_with(|| similarity_groups(&tracks, question_type));
+            pick_distractors(
+                rng,
+                &mut sampler,
+                &mut choices,
+                groups,
+                num_choices,
+                difficulty,
+            );
+            picked = choices.choices;
+            picked.shuffle(rng);
+            answer_pos = picked
+                .iter()
+                .position(|&(_, index)| index == answer)
+                .unwrap_or_default();
         }
-        let song_url = choices[ans_id].preview_url.to_string();
-        seen_urls.insert(song_url.clone(), i);
+
+        // tracks can be picked in an order leaving fewer choices than were counted
+        if !question_type.is_numeric() && picked.len() < required {
+            return Err(GenerateError::NotEnoughChoices {
+                question_type,
+                found: picked.len(),
+                required,
+            });
+        }
+
+        let (choices, ans_id, distractors) = match question_type {
+            QuestionType::Year | QuestionType::Decade => {
+                // sampled tracks of year and decade questions always have a release year
+                let year = tracks[answer].year.unwrap_or_default();
+                let (choices, ans_id) =
+                    question_type.gen_year_choices(rng, year, max_year, num_choices, difficulty);
+                (choices, ans_id, Vec::new())
+            }
+            QuestionType::Song | QuestionType::Album | QuestionType::Artist => {
+                let distractors = picked
+                    .iter()
+                    .map(|(_, index)| *index)
+                    .filter(|&index| index != answer)
+                    .collect();
+                let choices = picked.into_iter().map(|(value, _)| value).collect();
+                (choices, answer_pos, distractors)
+            }
+        };
+
+        let snippet = tracks[answer].snippet.clone();
+        seen_songs.insert(tracks[answer].song_key(), i);
+        let answer_artists = match question_type {
+            QuestionType::Artist => tracks[answer].artist_names.clone(),
+            _ => Vec::new(),
+        };
 
         // construct question for the current round
         let question = Question {
             question_type,
-            choices: choices.iter().map(|c| c.value.to_string()).collect(),
-            song_url,
+            choices,
+            snippet,
             ans_id,
-            score,
-            bonus: score / 5,
+            score: rules.question_score(i),
+            bonus: rules.bonus(rules.question_score(i)),
+            partial_credit: partial_credit && question_type.is_numeric(),
+            answer_mode,
+            answer_artists,
         };
-        if score + 100 <= SCORE_LIMIT {
-            score += 100;
-        }
         questions.push(question);
 
         // update weight for tracks that are selected as choices
-        let penalties = choices
-            .into_iter()
-            .enumerate()
-            .map(|(index, choice)| {
-                let penalty = if index != ans_id {
-                    rng.gen_range(7..=10)
-                } else {
-                    20
-                };
-                (penalty, choice.index)
-            })
+        let penalties = std::iter::once((20, answer))
+            .chain(
+                distractors
+                    .into_iter()
+                    .map(|index| (rng.gen_range(7..=10), index)),
+            )
             .collect::<Vec<_>>();
         for (penalty, index) in penalties {
-            tracks[index].weight -= penalty;
+            // keep a minimum weight so that every track can still be picked
+            let weight = sampler.weight(index).saturating_sub(penalty).max(1);
+            sampler.set(index, weight);
+        }
+    }
+
+    Ok(questions)
+}
+
+/// Check that questions of the given type with at least `required` choices
+/// can be generated from the tracks
+fn check_choices(
+    tracks: &[Track],
+    question_type: QuestionType,
+    required: usize,
+) -> Result<(), GenerateError> {
+    // choices of year and decade questions are generated around the answer's release year
+    if question_type.is_numeric() {
+        return if tracks.iter().any(|t| t.year.is_some()) {
+            Ok(())
+        } else {
+            Err(GenerateError::NoReleaseYears { question_type })
+        };
+    }
+
+    // values are counted the way choices are picked, so that tracks of the same song
+    // aren't counted twice. They are only counted up to the minimum to avoid scanning
+    // all tracks.
+    let mut picked = Picked::new(tracks, question_type);
+    for index in 0..tracks.len() {
+        picked.add(index);
+        if picked.choices.len() >= required {
+            return Ok(());
+        }
+    }
+    Err(GenerateError::NotEnoughChoices {
+        question_type,
+        found: picked.choices.len(),
+        required,
+    })
+}
+
+/// Indices of the tracks in each similarity group of the question type
+fn similarity_groups(tracks: &[Track], question_type: QuestionType) -> HashMap<String, Vec<usize>> {
+    let mut groups: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, track) in tracks.iter().enumerate() {
+        if let Some(group) = question_type.similarity_group(track) {
+            groups.entry(group).or_default().push(index);
+        }
+    }
+    groups
+}
+
+/// Tracks picked as the choices of a question
+struct Picked<'a> {
+    tracks: &'a [Track],
+    question_type: QuestionType,
+    // values of the picked tracks along with their indices
+    choices: Vec<(String, usize)>,
+    // keys of the picked values and songs
+    keys: Vec<(String, String)>,
+}
+
+impl<'a> Picked<'a> {
+    fn new(tracks: &'a [Track], question_type: QuestionType) -> Self {
+        Self {
+            tracks,
+            question_type,
+            choices: Vec::new(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Pick a track unless its value, up to versions of the same work, is already picked.
+    /// Tracks of the same song aren't both picked either, since the song would then
+    /// belong to more than one choice.
+    fn add(&mut self, index: usize) {
+        let track = &self.tracks[index];
+        let Some(key) = self.question_type.track_key(track) else {
+            return;
+        };
+        let song_key = track.song_key();
+        if self.keys.iter().any(|(k, s)| *k == key || *s == song_key) {
+            return;
+        }
+        // values exist whenever keys do
+        let value = self.question_type.track_value(track).unwrap_or_default();
+        self.choices.push((value, index));
+        self.keys.push((key, song_key));
+    }
+}
+
+/// Sample tracks accepted by `accept` proportionally to their weights,
+/// until `num_choices` tracks are picked or all tracks are sampled
+fn sample_tracks(
+    rng: &mut impl Rng,
+    sampler: &mut WeightedSampler,
+    picked: &mut Picked,
+    num_choices: usize,
+    accept: impl Fn(&Track) -> bool,
+) {
+    // sampled tracks are excluded until all tracks are picked
+    let mut excluded = Vec::new();
+    while picked.choices.len() < num_choices {
+        let Some(index) = sampler.sample(rng) else {
+            break;
+        };
+        if accept(&picked.tracks[index]) {
+            picked.add(index);
         }
+        excluded.push((index, sampler.weight(index)));
+        sampler.set(index, 0);
+    }
+    for (index, weight) in excluded {
+        sampler.set(index, weight);
+    }
+}
+
+/// Pick the distractors of a question whose answer is the only picked track, choosing
+/// tracks similar to the answer on hard difficulty and dissimilar ones on easy difficulty.
+/// Any other tracks are picked if there aren't enough such tracks.
+fn pick_distractors(
+    rng: &mut impl Rng,
+    sampler: &mut WeightedSampler,
+    picked: &mut Picked,
+    groups: &HashMap<String, Vec<usize>>,
+    num_choices: usize,
+    difficulty: Difficulty,
+) {
+    let question_type = picked.question_type;
+    let group = question_type.similarity_group(&picked.tracks[picked.choices[0].1]);
+
+    match difficulty {
+        Difficulty::Hard => {
+            // tracks of the answer's group, in a random order weighted by their weights
+            let similar = group
+                .and_then(|group| groups.get(&group))
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            if let Ok(similar) =
+                similar.choose_multiple_weighted(rng, similar.len(), |&i| sampler.weight(i) as f64)
+            {
+                for &index in similar {
+                    if picked.choices.len() >= num_choices {
+                        break;
+                    }
+                    picked.add(index);
+                }
+            }
+        }
+        Difficulty::Easy => {
+            // tracks without a group are as dissimilar as any other
+            sample_tracks(rng, sampler, picked, num_choices, |track| {
+                group.is_none() || question_type.similarity_group(track) != group
+            });
+        }
+        Difficulty::Normal => {}
     }
 
-    questions
+    sample_tracks(rng, sampler, picked, num_choices, |_| true);
 }
 
 pub fn gen_id(len: usize) -> String {
@@ -432,3 +1244,778 @@ pub fn gen_id(len: usize) -> String {
         .map(char::from)
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{MemorySource, MusicSource};
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::time::Duration;
+
+    /// Tracks of `num_artists` artists with 10 songs each, on 2 albums per artist,
+    /// where each artist's songs are named after the artist
+    fn tracks(num_artists: usize) -> Vec<source::Track> {
+        (0..num_artists)
+            .flat_map(|a| {
+                (0..10).map(move |s| source::Track {
+                    id: format!("{a}-{s}"),
+                    name: format!("Artist {a} Song {s}"),
+                    album: format!("Artist {a} Album {}", s / 5),
+                    artists: vec![format!("Artist {a}")],
+                    year: Some(1960 + 10 * a as i32 + s),
+                    popularity: 50,
+                    isrc: None,
+                    snippet: Some(Snippet::Url(format!("https://example.com/{a}-{s}.mp3"))),
+                })
+            })
+            .collect()
+    }
+
+    fn playlist() -> Collection {
+        Collection::Playlist {
+            id: "playlist".to_string(),
+        }
+    }
+
+    fn origin(num_questions: usize, question_types: Vec<QuestionType>) -> GameOrigin {
+        GameOrigin::Generated {
+            sources: vec![GameSource {
+                collection: playlist(),
+                weight: 1.0,
+            }],
+            num_questions,
+            question_types,
+            seed: 0,
+            options: GenerateOptions::default(),
+        }
+    }
+
+    /// Timing of the games in test rooms
+    const SETTINGS: GameSettings = GameSettings {
+        answer_time_ms: 5000,
+        reveal_time_ms: 1000,
+        pre_roll_ms: 2000,
+        scoring: ScoringPolicy::Escalating,
+        wrong_answer_penalty_percent: 0,
+        streak_bonus: StreakBonus::None,
+    };
+
+    /// A room of two users playing a game of tracks from an in-memory source
+    async fn room(num_questions: usize) -> Room {
+        let source = MemorySource::new(tracks(5));
+        let tracks = source.tracks(&playlist(), false).await.unwrap();
+        let question_types = vec![QuestionType::Song, QuestionType::Artist];
+        let mut rng = StdRng::seed_from_u64(0);
+        let questions = gen_questions(
+            &mut rng,
+            tracks,
+            num_questions,
+            question_types.clone(),
+            &GenerateOptions::default(),
+            &ScoringRules::default(),
+        )
+        .unwrap();
+
+        let room = Room::new("owner".to_string());
+        room.on_user_join("owner", "Owner");
+        room.on_user_join("guest", "Guest");
+        room.new_game(origin(num_questions, question_types), questions, SETTINGS);
+        room
+    }
+
+    /// Index of the current question and whether it is still played,
+    /// `None` if no game is played
+    fn current(room: &Room) -> Option<(usize, bool)> {
+        match &*room.game.read() {
+            GameState::Playing(state) => Some((
+                state.question_state.id,
+                state.question_state.status == QuestionStatus::Playing,
+            )),
+            _ => None,
+        }
+    }
+
+    fn answer(room: &Room) -> usize {
+        match &*room.game.read() {
+            GameState::Playing(state) => state.current_question().ans_id,
+            _ => panic!("no game is played"),
+        }
+    }
+
+    fn submit(room: &Room, user_id: &str, choice: usize, submitted_at_ms: u32) {
+        if let GameState::Playing(state) = &mut *room.game.write() {
+            state.question_state.submissions.push(UserSubmission {
+                user_name: user_id.to_string(),
+                user_id: user_id.to_string(),
+                choice: Some(choice),
+                text: None,
+                score: None,
+                verdict: None,
+                submitted_at_ms,
+            });
+        }
+    }
+
+    /// Move the current question's timer back as if the given time had passed
+    fn wait(room: &Room, duration: Duration) {
+        if let GameState::Playing(state) = &mut *room.game.write() {
+            state.question_state.timer -= duration;
+        }
+    }
+
+    fn score(room: &Room, user_id: &str) -> u64 {
+        let users = room.users();
+        users.iter().find(|u| u.id == user_id).unwrap().score
+    }
+
+    #[tokio::test]
+    async fn room_scores_answers() {
+        let room = room(3).await;
+        assert_eq!(current(&room), Some((0, true)));
+
+        let answer = answer(&room);
+        submit(&room, "guest", (answer + 1) % 4, 500);
+        submit(&room, "owner", answer, 1000);
+        room.on_question_end();
+        assert_eq!(current(&room), Some((0, false)));
+        assert!(score(&room, "owner") > 0);
+        assert_eq!(score(&room, "guest"), 0);
+
+        // questions only end once
+        let owner_score = score(&room, "owner");
+        room.on_question_end();
+        assert_eq!(score(&room, "owner"), owner_score);
+    }
+
+    #[tokio::test]
+    async fn penalties_dont_take_scores_below_zero() {
+        let room = room(2).await;
+        if let GameState::Playing(state) = &mut *room.game.write() {
+            state.settings.wrong_answer_penalty_percent = 50;
+        }
+
+        let first = answer(&room);
+        submit(&room, "owner", first, 0);
+        submit(&room, "guest", (first + 1) % 4, 0);
+        room.on_question_end();
+        let owner_score = score(&room, "owner");
+        assert_eq!(score(&room, "guest"), 0);
+
+        room.on_question_next();
+        let second = answer(&room);
+        submit(&room, "owner", (second + 1) % 4, 0);
+        room.on_question_end();
+        // unanswered questions lose nothing
+        assert_eq!(score(&room, "guest"), 0);
+        let penalty = owner_score - score(&room, "owner");
+        assert!(penalty > 0 && penalty < owner_score, "{penalty}");
+    }
+
+    #[tokio::test]
+    async fn streaks_of_correct_answers() {
+        let room = room(3).await;
+        if let GameState::Playing(state) = &mut *room.game.write() {
+            state.settings.streak_bonus = StreakBonus::Points {
+                step: 100,
+                max: 500,
+            };
+        }
+        let streak = |user_id: &str| {
+            let users = room.users();
+            users.iter().find(|u| u.id == user_id).unwrap().streak
+        };
+
+        let mut scores = Vec::new();
+        for _ in 0..2 {
+            let choice = answer(&room);
+            submit(&room, "owner", choice, 0);
+            submit(&room, "guest", choice, 0);
+            let before = score(&room, "guest");
+            room.on_question_end();
+            scores.push(score(&room, "guest") - before);
+            room.on_question_next();
+        }
+        assert_eq!((streak("owner"), streak("guest")), (2, 2));
+        // the second correct answer continues the streak, whose bonus is added
+        let escalation = ScoringPolicy::Escalating.rules().score_step;
+        assert_eq!(scores[1], scores[0] + escalation + 100);
+
+        // wrong answers and unanswered questions end streaks
+        let choice = answer(&room);
+        submit(&room, "owner", (choice + 1) % 4, 0);
+        room.on_question_end();
+        assert_eq!((streak("owner"), streak("guest")), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn reset_clears_scores_and_streaks() {
+        let room = room(3).await;
+        let choice = answer(&room);
+        submit(&room, "owner", choice, 0);
+        room.on_question_end();
+        let owner = |room: &Room| {
+            let users = room.users();
+            let owner = users.iter().find(|u| u.id == "owner").unwrap();
+            (owner.score, owner.streak)
+        };
+        assert!(owner(&room).0 > 0 && owner(&room).1 == 1);
+
+        room.reset();
+        assert!(matches!(*room.game.read(), GameState::Waiting));
+        assert_eq!(owner(&room), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn room_moves_through_questions_over_time() {
+        let room = room(2).await;
+        let question_time = Duration::from_millis(SETTINGS.pre_roll_ms + SETTINGS.answer_time_ms);
+        let reveal_time = Duration::from_millis(SETTINGS.reveal_time_ms);
+        let margin = Duration::from_millis(100);
+
+        // unanswered questions end once the pre-roll and the answer time are up
+        wait(&room, question_time - margin);
+        room.periodic_update();
+        assert_eq!(current(&room), Some((0, true)));
+        wait(&room, margin * 2);
+        room.periodic_update();
+        assert_eq!(current(&room), Some((0, false)));
+        assert_eq!(score(&room, "owner"), 0);
+
+        // the answer is shown for the reveal time before the next question
+        wait(&room, reveal_time - margin);
+        room.periodic_update();
+        assert_eq!(current(&room), Some((0, false)));
+        wait(&room, margin * 2);
+        room.periodic_update();
+        assert_eq!(current(&room), Some((1, true)));
+
+        // the game ends after the last question
+        wait(&room, question_time + margin);
+        room.periodic_update();
+        wait(&room, reveal_time + margin);
+        room.periodic_update();
+        assert!(matches!(
+            &*room.game.read(),
+            GameState::Ended {
+                origin: GameOrigin::Generated {
+                    num_questions: 2,
+                    ..
+                },
+                questions,
+                settings,
+            } if questions.len() == 2 && settings.answer_time_ms == SETTINGS.answer_time_ms
+        ));
+    }
+
+    #[test]
+    fn source_weights_scale_pick_weights() {
+        let unpopular = |tracks: Vec<source::Track>| {
+            tracks
+                .into_iter()
+                .map(|t| source::Track { popularity: 0, ..t })
+                .collect::<Vec<_>>()
+        };
+        let all = unpopular(tracks(2));
+        let (first, second) = all.split_at(10);
+        let merged = merge_source_tracks(vec![
+            (1.0, first.to_vec()),
+            (3.0, second.to_vec()),
+            // tracks found in several sources keep their highest weight
+            (2.0, first[..5].to_vec()),
+        ]);
+        let popularity: Vec<_> = merged.iter().map(|t| t.popularity).collect();
+        assert_eq!(popularity[..5], [1; 5]);
+        assert_eq!(popularity[5..10], [0; 5]);
+        assert_eq!(popularity[10..], [2; 10]);
+    }
+
+    #[test]
+    fn settings_are_validated() {
+        assert!(GameSettings::default().validate().is_ok());
+        assert!(SETTINGS.validate().is_ok());
+        let err = GameSettings {
+            answer_time_ms: 1000,
+            ..SETTINGS
+        }
+        .validate()
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "answer time must be between 3000 ms and 30000 ms, not 1000 ms"
+        );
+        assert!(GameSettings {
+            pre_roll_ms: 60_000,
+            ..SETTINGS
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn new_game_resets_scores_and_drops_offline_users() {
+        let room = room(2).await;
+        let answer = answer(&room);
+        submit(&room, "owner", answer, 0);
+        room.on_question_end();
+        room.on_user_leave("guest", false);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let questions = gen_questions(
+            &mut rng,
+            tracks(5),
+            2,
+            vec![QuestionType::Album],
+            &GenerateOptions::default(),
+            &ScoringRules::default(),
+        )
+        .unwrap();
+        room.new_game(origin(2, vec![QuestionType::Album]), questions, SETTINGS);
+        let users = room.users();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].id, "owner");
+        assert_eq!(users[0].score, 0);
+        assert_eq!(current(&room), Some((0, true)));
+    }
+
+    #[test]
+    fn same_questions_from_same_seed() {
+        let generate = |seed: u64| {
+            let mut rng = seeded_rng(seed);
+            let question_types = vec![
+                QuestionType::Song,
+                QuestionType::Album,
+                QuestionType::Artist,
+            ];
+            gen_questions(
+                &mut rng,
+                tracks(5),
+                20,
+                question_types,
+                &GenerateOptions::default(),
+                &ScoringRules::default(),
+            )
+            .unwrap()
+            .into_iter()
+            .map(|q| (q.choices, q.ans_id))
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(generate(1), generate(1));
+        assert_ne!(generate(1), generate(2));
+    }
+
+    #[test]
+    fn questions_of_a_seed_are_pinned() {
+        // a seed must generate the same questions in every version of the server
+        let questions = gen_questions(
+            &mut seeded_rng(42),
+            tracks(3),
+            4,
+            vec![QuestionType::Song, QuestionType::Year],
+            &GenerateOptions::default(),
+            &ScoringRules::default(),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|q| (q.question_type.to_string(), q.choices, q.ans_id))
+        .collect::<Vec<_>>();
+        let expected = [
+            ("year", ["1970", "1968", "1966", "1967"], 1),
+            (
+                "song",
+                [
+                    "Artist 0 Song 5",
+                    "Artist 1 Song 8",
+                    "Artist 2 Song 9",
+                    "Artist 0 Song 9",
+                ],
+                0,
+            ),
+            (
+                "song",
+                [
+                    "Artist 0 Song 1",
+                    "Artist 0 Song 2",
+                    "Artist 2 Song 5",
+                    "Artist 2 Song 1",
+                ],
+                3,
+            ),
+            (
+                "song",
+                [
+                    "Artist 1 Song 9",
+                    "Artist 0 Song 3",
+                    "Artist 1 Song 1",
+                    "Artist 2 Song 6",
+                ],
+                3,
+            ),
+        ]
+        .map(|(question_type, choices, ans_id)| {
+            (
+                question_type.to_string(),
+                choices.map(String::from).to_vec(),
+                ans_id,
+            )
+        });
+        assert_eq!(questions, expected);
+    }
+
+    fn generate(
+        tracks: Vec<source::Track>,
+        question_types: &[QuestionType],
+        options: GenerateOptions,
+    ) -> Result<Vec<Question>, GenerateError> {
+        let mut rng = StdRng::seed_from_u64(0);
+        gen_questions(
+            &mut rng,
+            tracks,
+            20,
+            question_types.to_vec(),
+            &options,
+            &ScoringRules::default(),
+        )
+    }
+
+    #[test]
+    fn generated_questions_have_distinct_choices() {
+        let types = [
+            QuestionType::Song,
+            QuestionType::Album,
+            QuestionType::Artist,
+            QuestionType::Year,
+            QuestionType::Decade,
+        ];
+        for num_choices in [MIN_CHOICES, 4, 5] {
+            let questions = generate(tracks(5), &types, with_choices(num_choices)).unwrap();
+            assert_eq!(questions.len(), 20);
+            for question in &questions {
+                let distinct = question.choices.iter().collect::<HashSet<_>>();
+                assert_eq!(distinct.len(), num_choices, "{question:?}");
+                assert!(question.ans_id < num_choices);
+            }
+        }
+    }
+
+    #[test]
+    fn generate_errors() {
+        let options = GenerateOptions::default;
+        let mut rng = StdRng::seed_from_u64(0);
+        let err = gen_questions(
+            &mut rng,
+            tracks(1),
+            0,
+            vec![QuestionType::Song],
+            &options(),
+            &ScoringRules::default(),
+        );
+        assert!(matches!(err, Err(GenerateError::NoQuestions)));
+        assert!(matches!(
+            generate(tracks(1), &[], options()),
+            Err(GenerateError::NoQuestionTypes)
+        ));
+        assert!(matches!(
+            generate(tracks(1), &[QuestionType::Song], with_choices(1)),
+            Err(GenerateError::InvalidNumChoices { num_choices: 1 })
+        ));
+        assert!(matches!(
+            generate(
+                tracks(1),
+                &[QuestionType::Song],
+                with_choices(MAX_CHOICES + 1)
+            ),
+            Err(GenerateError::InvalidNumChoices { .. })
+        ));
+
+        let mut unplayable = tracks(1);
+        for track in &mut unplayable[1..] {
+            track.snippet = None;
+        }
+        assert!(matches!(
+            generate(unplayable, &[QuestionType::Song], options()),
+            Err(GenerateError::NotEnoughTracks {
+                found: 1,
+                required: MIN_CHOICES,
+            })
+        ));
+
+        // a single artist's songs only have one artist
+        assert!(matches!(
+            generate(tracks(1), &[QuestionType::Artist], options()),
+            Err(GenerateError::NotEnoughChoices {
+                question_type: QuestionType::Artist,
+                found: 1,
+                required: MIN_CHOICES,
+            })
+        ));
+        // a single artist has 2 albums
+        assert!(matches!(
+            generate(tracks(1), &[QuestionType::Album], with_choices(3)),
+            Err(GenerateError::NotEnoughChoices {
+                question_type: QuestionType::Album,
+                found: 2,
+                required: 3,
+            })
+        ));
+
+        let mut undated = tracks(1);
+        for track in &mut undated {
+            track.year = None;
+        }
+        assert!(matches!(
+            generate(undated, &[QuestionType::Decade], options()),
+            Err(GenerateError::NoReleaseYears {
+                question_type: QuestionType::Decade,
+            })
+        ));
+    }
+
+    #[test]
+    fn types_without_enough_choices_are_skipped() {
+        let types = [QuestionType::Song, QuestionType::Artist];
+        // without a number of choices, the single artist's questions are skipped
+        let questions = generate(tracks(1), &types, GenerateOptions::default()).unwrap();
+        assert_eq!(questions.len(), 20);
+        assert!(questions
+            .iter()
+            .all(|q| matches!(q.question_type, QuestionType::Song)));
+        // with one, they fail the game
+        assert!(matches!(
+            generate(tracks(1), &types, with_choices(2)),
+            Err(GenerateError::NotEnoughChoices {
+                question_type: QuestionType::Artist,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn versions_of_a_song_are_one_choice() {
+        let mut tracks = tracks(1);
+        tracks[1].name = "Artist 0 Song 0 - 2011 Remaster".to_string();
+        tracks[2].name = "Artist 0 Song 0 (Live)".to_string();
+        let questions =
+            generate(tracks, &[QuestionType::Song], GenerateOptions::default()).unwrap();
+        for question in questions {
+            let works = question
+                .choices
+                .iter()
+                .map(|choice| title::key(choice))
+                .collect::<HashSet<_>>();
+            assert_eq!(works.len(), question.choices.len(), "{question:?}");
+            assert!(!question.choices.iter().any(|c| c.contains("Remaster")));
+        }
+    }
+
+    fn with_choices(num_choices: usize) -> GenerateOptions {
+        GenerateOptions {
+            num_choices: Some(num_choices),
+            ..GenerateOptions::default()
+        }
+    }
+
+    fn multiple_choice(question_type: QuestionType, choices: &[&str], ans_id: usize) -> Question {
+        Question {
+            question_type,
+            choices: choices.iter().map(|c| c.to_string()).collect(),
+            snippet: Snippet::Url("https://example.com/a.mp3".to_string()),
+            score: 1000,
+            bonus: 200,
+            ans_id,
+            partial_credit: false,
+            answer_mode: AnswerMode::MultipleChoice,
+            answer_artists: Vec::new(),
+        }
+    }
+
+    fn submission(choice: Option<usize>, text: Option<&str>) -> UserSubmission {
+        UserSubmission {
+            user_name: "user".to_string(),
+            user_id: "user".to_string(),
+            choice,
+            text: text.map(String::from),
+            score: None,
+            verdict: None,
+            submitted_at_ms: 0,
+        }
+    }
+
+    #[test]
+    fn partial_credit_of_near_misses() {
+        let mut question =
+            multiple_choice(QuestionType::Year, &["1990", "1991", "1992", "1994"], 0);
+        let score = |question: &Question, choice: usize| {
+            question.submission_score(
+                &submission(Some(choice), None),
+                false,
+                0,
+                &GameSettings::default(),
+            )
+        };
+        assert_eq!(score(&question, 0), 1000);
+        assert_eq!(score(&question, 1), 0);
+
+        question.partial_credit = true;
+        assert_eq!(score(&question, 1), 500);
+        assert_eq!(score(&question, 2), 250);
+        assert_eq!(score(&question, 3), 0);
+    }
+
+    #[test]
+    fn wrong_answers_lose_the_penalty() {
+        let question = multiple_choice(QuestionType::Song, &["a", "b"], 0);
+        let settings = GameSettings {
+            wrong_answer_penalty_percent: 25,
+            ..GameSettings::default()
+        };
+        let score =
+            |choice| question.submission_score(&submission(choice, None), false, 0, &settings);
+        assert_eq!(score(Some(0)), 1000);
+        assert_eq!(score(Some(1)), -250);
+        assert_eq!(score(None), -250);
+    }
+
+    #[test]
+    fn credit_of_multiple_choice() {
+        let mut question = multiple_choice(QuestionType::Year, &["1990", "1991", "1994"], 0);
+        assert_eq!(question.credit(&submission(Some(0), None)), Some(0));
+        assert_eq!(question.credit(&submission(Some(1), None)), None);
+        assert_eq!(question.credit(&submission(None, None)), None);
+
+        question.partial_credit = true;
+        assert_eq!(question.credit(&submission(Some(1), None)), Some(1));
+        assert_eq!(question.verdict(&submission(Some(1), None)), Verdict::Close);
+        assert_eq!(question.credit(&submission(Some(2), None)), None);
+    }
+
+    #[test]
+    fn credit_of_typed_answers() {
+        let mut question = multiple_choice(QuestionType::Artist, &["Simon & Garfunkel, Queen"], 0);
+        question.answer_mode = AnswerMode::FreeText;
+        question.answer_artists = vec!["Simon & Garfunkel".to_string(), "Queen".to_string()];
+        let credit =
+            |question: &Question, text: &str| question.credit(&submission(None, Some(text)));
+        assert_eq!(credit(&question, "simon and garfunkel, queen"), Some(0));
+        // naming one of the artists is enough
+        assert_eq!(credit(&question, "queen"), Some(0));
+        assert_eq!(credit(&question, "Simon Garfunkel"), Some(1));
+        assert_eq!(credit(&question, "ABBA"), None);
+        // artists are only the answer's artists, not parts of their names
+        let mut band = multiple_choice(QuestionType::Artist, &["Earth, Wind & Fire"], 0);
+        band.answer_mode = AnswerMode::FreeText;
+        band.answer_artists = vec!["Earth, Wind & Fire".to_string()];
+        assert_eq!(credit(&band, "earth wind and fire"), Some(0));
+        assert_eq!(credit(&band, "Earth"), None);
+
+        let mut decade = multiple_choice(QuestionType::Decade, &["1990s"], 0);
+        decade.answer_mode = AnswerMode::FreeText;
+        decade.partial_credit = true;
+        assert_eq!(credit(&decade, "1990"), Some(0));
+        assert_eq!(credit(&decade, "1990's"), Some(0));
+        assert_eq!(credit(&decade, "1980s"), Some(1));
+        assert_eq!(credit(&decade, "1960s"), None);
+        assert_eq!(credit(&decade, "nineties"), None);
+    }
+
+    #[test]
+    fn versions_of_a_song_are_counted_once() {
+        let track = |name: &str, album: &str| source::Track {
+            id: name.to_string(),
+            name: name.to_string(),
+            album: album.to_string(),
+            artists: vec!["Artist".to_string()],
+            year: None,
+            popularity: 50,
+            isrc: None,
+            snippet: Some(Snippet::Url(format!("https://example.com/{album}.mp3"))),
+        };
+        // 3 albums, but the first two only have versions of the same song
+        let tracks = vec![
+            track("Song", "Album 1"),
+            track("Song - 2011 Remaster", "Album 2"),
+            track("Other Song", "Album 3"),
+        ];
+        assert!(matches!(
+            generate(tracks, &[QuestionType::Album], with_choices(3)),
+            Err(GenerateError::NotEnoughChoices {
+                question_type: QuestionType::Album,
+                found: 2,
+                required: 3,
+            })
+        ));
+    }
+
+    #[test]
+    fn distractors_by_difficulty() {
+        let artist = |choice: &str| choice.split(" Song ").next().unwrap().to_string();
+        for (difficulty, same_artist) in [(Difficulty::Hard, true), (Difficulty::Easy, false)] {
+            let options = GenerateOptions {
+                difficulty,
+                ..with_choices(4)
+            };
+            let questions = generate(tracks(5), &[QuestionType::Song], options).unwrap();
+            for question in questions {
+                let answer = artist(&question.choices[question.ans_id]);
+                for (i, choice) in question.choices.iter().enumerate() {
+                    if i != question.ans_id {
+                        assert_eq!(artist(choice) == answer, same_artist, "{question:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn year_choices_by_difficulty() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for (difficulty, min_distance, max_distance) in [
+            (Difficulty::Hard, 1, 3),
+            (Difficulty::Normal, 1, 10),
+            (Difficulty::Easy, PARTIAL_CREDIT_DISTANCE + 1, 40),
+        ] {
+            for _ in 0..100 {
+                let (choices, ans_id) =
+                    QuestionType::Year.gen_year_choices(&mut rng, 1994, 2020, 4, difficulty);
+                assert_eq!(choices[ans_id], "1994");
+                let years = choices
+                    .iter()
+                    .map(|c| c.parse::<i32>().unwrap())
+                    .collect::<HashSet<_>>();
+                assert_eq!(years.len(), 4);
+                for year in years.into_iter().filter(|&year| year != 1994) {
+                    let distance = (year - 1994).abs();
+                    assert!(
+                        (min_distance..=max_distance).contains(&distance),
+                        "{difficulty:?} {choices:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn year_choices_are_before_latest_release() {
+        let mut rng = StdRng::seed_from_u64(0);
+        // the answer isn't always the latest choice when it is close to the latest release
+        let mut positions = HashSet::new();
+        for _ in 0..100 {
+            let (choices, ans_id) =
+                QuestionType::Year.gen_year_choices(&mut rng, 2019, 2020, 4, Difficulty::Hard);
+            let mut years = choices
+                .iter()
+                .map(|c| c.parse::<i32>().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(years[ans_id], 2019);
+            assert!(years.iter().all(|&year| year <= 2020), "{choices:?}");
+            years.sort_unstable();
+            positions.insert(years.iter().position(|&year| year == 2019));
+        }
+        assert!(positions.len() > 1, "{positions:?}");
+
+        let (choices, _) =
+            QuestionType::Decade.gen_year_choices(&mut rng, 2015, 2015, 4, Difficulty::Normal);
+        assert_eq!(choices.len(), 4);
+        assert!(choices.iter().all(|c| c.as_str() <= "2010s"), "{choices:?}");
+        assert!(choices.contains(&"2010s".to_string()));
+    }
+}
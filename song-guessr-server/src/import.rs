@@ -0,0 +1,359 @@
+use anyhow::Context;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::source::{MusicSource, SourceError, Track, TrackQuery};
+use crate::store::{NotFound, RecordDir};
+
+// maximum number of playlist entries looked up at the same time
+const MAX_CONCURRENT_LOOKUPS: usize = 4;
+const DEFAULT_PLAYLIST_NAME: &str = "Imported playlist";
+
+/// Format of a playlist file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// M3U or M3U8, optionally with `#EXTINF` lines
+    M3u,
+    Xspf,
+    /// A list of tracks, or an object with a `name` and a list of `tracks`
+    Json,
+}
+
+impl Format {
+    /// Guess the format of a playlist file from its content
+    fn detect(content: &str) -> Self {
+        match content.trim_start().chars().next() {
+            Some('<') => Self::Xspf,
+            Some('[' | '{') => Self::Json,
+            _ => Self::M3u,
+        }
+    }
+}
+
+/// Entries of a playlist file
+pub struct PlaylistFile {
+    pub name: Option<String>,
+    pub entries: Vec<TrackQuery>,
+}
+
+/// Parse a playlist file, detecting its format from the content if not given
+pub fn parse(content: &str, format: Option<Format>) -> anyhow::Result<PlaylistFile> {
+    match format.unwrap_or_else(|| Format::detect(content)) {
+        Format::M3u => Ok(parse_m3u(content)),
+        Format::Xspf => parse_xspf(content),
+        Format::Json => parse_json(content),
+    }
+}
+
+fn parse_m3u(content: &str) -> PlaylistFile {
+    let mut name = None;
+    let mut entries = Vec::new();
+    // artist and title of the next entry, from its `#EXTINF` line
+    let mut info = (None, None);
+
+    for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(playlist) = line.strip_prefix("#PLAYLIST:") {
+            name = Some(playlist.trim().to_string());
+        } else if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            // `#EXTINF:<duration>,<artist> - <title>`
+            let display = extinf.split_once(',').map_or("", |(_, d)| d.trim());
+            info = match display.split_once(" - ") {
+                Some((artist, title)) => (
+                    Some(artist.trim().to_string()),
+                    Some(title.trim().to_string()),
+                ),
+                None if !display.is_empty() => (None, Some(display.to_string())),
+                None => (None, None),
+            };
+        } else if !line.starts_with('#') {
+            let (artist, title) = std::mem::take(&mut info);
+            entries.push(TrackQuery {
+                location: Some(line.to_string()),
+                title,
+                artist,
+            });
+        }
+    }
+
+    PlaylistFile { name, entries }
+}
+
+#[derive(Debug, Deserialize)]
+struct Xspf {
+    title: Option<String>,
+    #[serde(rename = "trackList", default)]
+    track_list: XspfTrackList,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct XspfTrackList {
+    #[serde(default)]
+    track: Vec<XspfTrack>,
+}
+
+#[derive(Debug, Deserialize)]
+struct XspfTrack {
+    // a track can have multiple alternative locations
+    #[serde(default)]
+    location: Vec<String>,
+    title: Option<String>,
+    creator: Option<String>,
+}
+
+fn parse_xspf(content: &str) -> anyhow::Result<PlaylistFile> {
+    let playlist: Xspf = quick_xml::de::from_str(content).context("invalid XSPF playlist")?;
+    Ok(PlaylistFile {
+        name: playlist.title,
+        entries: playlist
+            .track_list
+            .track
+            .into_iter()
+            .map(|track| TrackQuery {
+                location: track.location.into_iter().next(),
+                title: track.title,
+                artist: track.creator,
+            })
+            .collect(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JsonPlaylist {
+    Tracks(Vec<TrackQuery>),
+    Playlist {
+        name: Option<String>,
+        tracks: Vec<TrackQuery>,
+    },
+}
+
+fn parse_json(content: &str) -> anyhow::Result<PlaylistFile> {
+    let playlist: JsonPlaylist = serde_json::from_str(content).context("invalid JSON playlist")?;
+    Ok(match playlist {
+        JsonPlaylist::Tracks(entries) => PlaylistFile {
+            name: None,
+            entries,
+        },
+        JsonPlaylist::Playlist { name, tracks } => PlaylistFile {
+            name,
+            entries: tracks,
+        },
+    })
+}
+
+/// A playlist imported from a file, with its entries resolved to tracks
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportedPlaylist {
+    pub id: String,
+    pub name: String,
+    pub tracks: Vec<Track>,
+    // room that the playlist was imported in, only the room's games can use it
+    #[serde(default)]
+    pub room_id: String,
+}
+
+/// Summary of an imported playlist
+#[derive(Debug, Serialize)]
+pub struct ImportedPlaylistInfo {
+    pub id: String,
+    pub name: String,
+    pub num_tracks: usize,
+}
+
+/// Imports playlist files by looking up their entries in music sources,
+/// and stores the imported playlists on disk
+pub struct Importer {
+    records: RecordDir,
+    // music sources that entries are looked up in, in order
+    sources: Vec<Arc<dyn MusicSource>>,
+}
+
+impl Importer {
+    pub fn new(dir: PathBuf, sources: Vec<Arc<dyn MusicSource>>) -> anyhow::Result<Self> {
+        let records = RecordDir::new(dir, "imported playlist")?;
+        Ok(Self { records, sources })
+    }
+
+    /// Resolve the entries of a playlist file and store the result as a new imported playlist.
+    /// Also returns the entries that no music source has a track for.
+    pub async fn import(
+        &self,
+        file: PlaylistFile,
+        name: Option<String>,
+        room_id: &str,
+    ) -> anyhow::Result<(ImportedPlaylist, Vec<TrackQuery>)> {
+        let results = futures::stream::iter(file.entries)
+            .map(|entry| async move {
+                let track = self.find_track(&entry).await;
+                (entry, track)
+            })
+            .buffered(MAX_CONCURRENT_LOOKUPS)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut tracks = Vec::new();
+        let mut unresolved = Vec::new();
+        for (entry, track) in results {
+            match track? {
+                Some(track) => tracks.push(track),
+                None => unresolved.push(entry),
+            }
+        }
+
+        let playlist = ImportedPlaylist {
+            id: crate::game::gen_id(8),
+            name: name
+                .or(file.name)
+                .unwrap_or_else(|| DEFAULT_PLAYLIST_NAME.to_string()),
+            tracks,
+            room_id: room_id.to_string(),
+        };
+        self.records.write(&playlist.id, &playlist).await?;
+        Ok((playlist, unresolved))
+    }
+
+    /// Find the track of an entry in the first music source that has it.
+    /// Only errors of the music sources themselves, e.g. rate limits, are returned,
+    /// other errors such as invalid track IDs mean that the entry can't be resolved.
+    async fn find_track(&self, entry: &TrackQuery) -> anyhow::Result<Option<Track>> {
+        for source in &self.sources {
+            match source.find_track(entry).await {
+                Ok(Some(track)) => return Ok(Some(track)),
+                Ok(None) => {}
+                Err(err) if err.downcast_ref::<SourceError>().is_some() => return Err(err),
+                Err(_) => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Get a playlist imported in the given room
+    pub async fn get(&self, id: &str, room_id: &str) -> anyhow::Result<ImportedPlaylist> {
+        let playlist: ImportedPlaylist = self.records.read(id).await?;
+        // playlists of other rooms are hidden rather than forbidden
+        if playlist.room_id != room_id {
+            return Err(NotFound {
+                kind: "imported playlist",
+                id: id.to_string(),
+            }
+            .into());
+        }
+        Ok(playlist)
+    }
+
+    /// List the playlists imported in the given room
+    pub async fn list(&self, room_id: &str) -> anyhow::Result<Vec<ImportedPlaylistInfo>> {
+        let imported = self.records.list::<ImportedPlaylist>().await?;
+        let mut playlists = imported
+            .into_iter()
+            .filter(|playlist| playlist.room_id == room_id)
+            .map(|playlist| ImportedPlaylistInfo {
+                id: playlist.id,
+                name: playlist.name,
+                num_tracks: playlist.tracks.len(),
+            })
+            .collect::<Vec<_>>();
+        playlists.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(playlists)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(entry: &TrackQuery) -> (Option<&str>, Option<&str>, Option<&str>) {
+        (
+            entry.location.as_deref(),
+            entry.title.as_deref(),
+            entry.artist.as_deref(),
+        )
+    }
+
+    #[test]
+    fn parse_m3u_with_extinf() {
+        let content = "#EXTM3U\n#PLAYLIST: Road trip\n\n#EXTINF:215,Queen - Don't Stop Me Now\nQueen/dont_stop.mp3\n#EXTINF:-1,Untitled\nhttps://example.com/a.mp3\nplain.mp3\n";
+        let playlist = parse(content, None).unwrap();
+        assert_eq!(playlist.name.as_deref(), Some("Road trip"));
+        let entries = playlist.entries.iter().map(entry).collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (
+                    Some("Queen/dont_stop.mp3"),
+                    Some("Don't Stop Me Now"),
+                    Some("Queen")
+                ),
+                (Some("https://example.com/a.mp3"), Some("Untitled"), None),
+                // `#EXTINF` lines only describe the next entry
+                (Some("plain.mp3"), None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_xspf_playlist() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <title>Favorites</title>
+  <trackList>
+    <track>
+      <location>file:///music/a.mp3</location>
+      <location>https://example.com/a.mp3</location>
+      <title>Yellow</title>
+      <creator>Coldplay</creator>
+    </track>
+    <track>
+      <title>Creep</title>
+    </track>
+  </trackList>
+</playlist>"#;
+        let playlist = parse(content, None).unwrap();
+        assert_eq!(playlist.name.as_deref(), Some("Favorites"));
+        let entries = playlist.entries.iter().map(entry).collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (
+                    Some("file:///music/a.mp3"),
+                    Some("Yellow"),
+                    Some("Coldplay")
+                ),
+                (None, Some("Creep"), None),
+            ]
+        );
+        assert!(parse("<playlist>", Some(Format::Xspf)).is_err());
+    }
+
+    #[test]
+    fn parse_json_playlist() {
+        let tracks = r#"[{"name": "Yellow", "artist": "Coldplay"}, {"uri": "spotify:track:1"}]"#;
+        let playlist = parse(tracks, None).unwrap();
+        assert_eq!(playlist.name, None);
+        let entries = playlist.entries.iter().map(entry).collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (None, Some("Yellow"), Some("Coldplay")),
+                (Some("spotify:track:1"), None, None),
+            ]
+        );
+
+        let playlist = parse(r#"{"name": "Mix", "tracks": [{"path": "a.mp3"}]}"#, None).unwrap();
+        assert_eq!(playlist.name.as_deref(), Some("Mix"));
+        assert_eq!(entry(&playlist.entries[0]), (Some("a.mp3"), None, None));
+        assert!(parse(r#"{"name": "Mix"}"#, None).is_err());
+    }
+
+    #[test]
+    fn detect_format() {
+        assert_eq!(Format::detect("  <?xml"), Format::Xspf);
+        assert_eq!(Format::detect("\n[]"), Format::Json);
+        assert_eq!(Format::detect("{}"), Format::Json);
+        assert_eq!(Format::detect("#EXTM3U"), Format::M3u);
+        assert_eq!(Format::detect("a.mp3"), Format::M3u);
+    }
+}
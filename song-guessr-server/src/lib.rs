@@ -0,0 +1,15 @@
+pub mod answer;
+pub mod api;
+pub mod audio;
+pub mod cache;
+pub mod client;
+pub mod game;
+pub mod import;
+pub mod library;
+pub mod pack;
+pub mod resolver;
+pub mod sampler;
+pub mod scoring;
+pub mod source;
+pub mod store;
+pub mod title;
@@ -0,0 +1,399 @@
+use anyhow::Context;
+use lofty::prelude::{Accessor, ItemKey, TaggedFileExt};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::audio;
+use crate::resolver::match_key;
+use crate::source::{
+    album_id, Collection, MusicSource, SearchItem, SearchKind, Snippet, SnippetAudio, Track,
+    TrackQuery,
+};
+
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "ogg", "oga", "opus", "m4a"];
+const PLAYLIST_EXTENSIONS: &[&str] = &["m3u", "m3u8"];
+const LIBRARY_OWNER: &str = "Local library";
+
+/// A music source backed by a directory of audio files on the server.
+///
+/// Every folder containing audio files and every M3U file in the directory tree
+/// is exposed as a playlist, identified by its path relative to the library's root.
+/// Albums are identified by their names and album artists in the files' tags,
+/// and artists by their names.
+pub struct Library {
+    root: PathBuf,
+    tracks: Vec<Track>,
+    // album IDs of the tracks, by the tracks' indices
+    album_ids: Vec<String>,
+    playlists: Vec<LibraryPlaylist>,
+}
+
+struct LibraryPlaylist {
+    id: String,
+    name: String,
+    // indices of the playlist's tracks in the library
+    tracks: Vec<usize>,
+}
+
+impl Library {
+    /// Scan the given directory and index all of its audio files
+    pub fn scan(root: &Path) -> anyhow::Result<Self> {
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("invalid music directory {}", root.display()))?;
+
+        let mut library = Self {
+            root: root.clone(),
+            tracks: Vec::new(),
+            album_ids: Vec::new(),
+            playlists: Vec::new(),
+        };
+        let mut playlist_files = Vec::new();
+        library.scan_dir(&root, &mut playlist_files, &mut HashSet::new())?;
+
+        let track_ids = library
+            .tracks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, track)| match &track.snippet {
+                Some(Snippet::File(path)) => Some((path.clone(), index)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        for path in playlist_files {
+            // skip unreadable playlists instead of failing the whole scan
+            if let Err(err) = library.load_m3u(&path, &track_ids) {
+                eprintln!("Skipping a playlist of the music library: {err:#}");
+            }
+        }
+
+        Ok(library)
+    }
+
+    /// Recursively index a directory, returning indices of the tracks inside it.
+    /// M3U files found along the way are collected to be loaded once all tracks are indexed.
+    /// Directories already scanned through symlinks are skipped, which also prevents symlink cycles.
+    fn scan_dir(
+        &mut self,
+        dir: &Path,
+        playlist_files: &mut Vec<PathBuf>,
+        visited: &mut HashSet<PathBuf>,
+    ) -> anyhow::Result<Vec<usize>> {
+        let canonical = dir
+            .canonicalize()
+            .with_context(|| format!("failed to read directory {}", dir.display()))?;
+        if !visited.insert(canonical) {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = std::fs::read_dir(dir)
+            .with_context(|| format!("failed to read directory {}", dir.display()))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+
+        let mut tracks = Vec::new();
+        for path in entries {
+            if path.is_dir() {
+                tracks.extend(self.scan_dir(&path, playlist_files, visited)?);
+            } else if has_extension(&path, PLAYLIST_EXTENSIONS) {
+                playlist_files.push(path);
+            } else if has_extension(&path, AUDIO_EXTENSIONS) {
+                // skip unreadable files instead of failing the whole scan
+                if let Ok((track, album_id)) = self.read_track(&path) {
+                    tracks.push(self.tracks.len());
+                    self.tracks.push(track);
+                    self.album_ids.push(album_id);
+                }
+            }
+        }
+
+        if !tracks.is_empty() {
+            let name = if dir == self.root {
+                "All tracks".to_string()
+            } else {
+                file_name(dir)
+            };
+            self.playlists.push(LibraryPlaylist {
+                id: self.relative_id(dir),
+                name,
+                tracks: tracks.clone(),
+            });
+        }
+
+        Ok(tracks)
+    }
+
+    fn load_m3u(&mut self, path: &Path, track_ids: &HashMap<PathBuf, usize>) -> anyhow::Result<()> {
+        let content = std::fs::read(path)
+            .with_context(|| format!("failed to read playlist {}", path.display()))?;
+        let content = String::from_utf8_lossy(&content);
+        let dir = path.parent().unwrap_or(&self.root);
+
+        let tracks = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| dir.join(line).canonicalize().ok())
+            .filter_map(|entry| track_ids.get(&entry).copied())
+            .collect::<Vec<_>>();
+
+        if !tracks.is_empty() {
+            self.playlists.push(LibraryPlaylist {
+                id: self.relative_id(path),
+                name: file_name(&path.with_extension("")),
+                tracks,
+            });
+        }
+        Ok(())
+    }
+
+    /// Read an audio file's track, along with the ID of its album
+    fn read_track(&self, path: &Path) -> anyhow::Result<(Track, String)> {
+        let file = lofty::read_from_path(path)?;
+        let tag = file.primary_tag().or_else(|| file.first_tag());
+
+        let title = tag.and_then(|t| t.title()).map(|s| s.to_string());
+        let artist = tag.and_then(|t| t.artist()).map(|s| s.to_string());
+        let album = tag.and_then(|t| t.album()).map(|s| s.to_string());
+        let album_artist = tag
+            .and_then(|t| t.get_string(ItemKey::AlbumArtist))
+            .map(|s| s.to_string());
+        let year = tag.and_then(|t| t.date()).map(|d| i32::from(d.year));
+        let isrc = tag
+            .and_then(|t| t.get_string(ItemKey::Isrc))
+            .map(|s| s.to_string());
+
+        let album = album.unwrap_or_else(|| path.parent().map(file_name).unwrap_or_default());
+        let artist = artist.unwrap_or_else(|| "Unknown artist".to_string());
+        let album_id = album_id(&album, album_artist.as_ref().unwrap_or(&artist));
+        let track = Track {
+            id: self.relative_id(path),
+            name: title.unwrap_or_else(|| file_name(&path.with_extension(""))),
+            album,
+            artists: vec![artist],
+            year,
+            // local files don't have popularity data, treat all tracks equally
+            popularity: 50,
+            isrc,
+            snippet: Some(Snippet::File(path.to_path_buf())),
+        };
+        Ok((track, album_id))
+    }
+
+    /// All tracks of the library
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Find the library's track at a location, see [`Self::location_id`]
+    pub fn find_file(&self, location: &str) -> Option<&Track> {
+        let id = self.location_id(location)?;
+        self.tracks.iter().find(|t| t.id == id)
+    }
+
+    /// Get the ID of the library's track at a location of a playlist file, which is either
+    /// a `file://` URL or a path that is absolute or relative to the library's root
+    fn location_id(&self, location: &str) -> Option<String> {
+        let path = match reqwest::Url::parse(location) {
+            Ok(url) if url.scheme() == "file" => url.to_file_path().ok()?,
+            // e.g. HTTP URLs, or Windows paths that are parsed as URLs
+            Ok(_) => return None,
+            Err(_) => PathBuf::from(location),
+        };
+        // canonicalize the path to prevent locations outside of the library
+        let path = self.root.join(path).canonicalize().ok()?;
+        path.starts_with(&self.root)
+            .then(|| self.relative_id(&path))
+    }
+
+    /// Get the location of an audio file relative to the library's root,
+    /// if the file is inside the library
+    pub fn relative_location(&self, path: &Path) -> Option<String> {
+        path.starts_with(&self.root).then(|| self.relative_id(path))
+    }
+
+    fn relative_id(&self, path: &Path) -> String {
+        let id = path
+            .strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy();
+        if id.is_empty() {
+            ".".to_string()
+        } else {
+            id.into_owned()
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl MusicSource for Library {
+    async fn search(&self, query: &str, kind: SearchKind) -> anyhow::Result<Vec<SearchItem>> {
+        let query = query.to_lowercase();
+        let matches = |s: &str| s.to_lowercase().contains(&query);
+
+        let items = match kind {
+            SearchKind::Playlist => self
+                .playlists
+                .iter()
+                .filter(|p| matches(&p.id) || matches(&p.name))
+                .map(|p| SearchItem::Playlist {
+                    id: p.id.clone(),
+                    name: p.name.clone(),
+                    owner: LIBRARY_OWNER.to_string(),
+                })
+                .collect(),
+            SearchKind::Album => {
+                let mut albums = HashSet::new();
+                self.tracks
+                    .iter()
+                    .zip(&self.album_ids)
+                    .filter(|(t, _)| matches(&t.album) || t.artists.iter().any(|a| matches(a)))
+                    .filter(|(_, id)| albums.insert(*id))
+                    .map(|(t, id)| SearchItem::Album {
+                        id: id.clone(),
+                        name: t.album.clone(),
+                        artists: t.artists.clone(),
+                        year: t.year,
+                    })
+                    .collect()
+            }
+            SearchKind::Artist => {
+                let mut artists = HashSet::new();
+                self.tracks
+                    .iter()
+                    .flat_map(|t| &t.artists)
+                    .filter(|a| matches(a) && artists.insert(*a))
+                    .map(|a| SearchItem::Artist {
+                        id: a.clone(),
+                        name: a.clone(),
+                    })
+                    .collect()
+            }
+            SearchKind::Track => self
+                .tracks
+                .iter()
+                .filter(|t| matches(&t.name) || t.artists.iter().any(|a| matches(a)))
+                .map(|t| SearchItem::Track {
+                    id: t.id.clone(),
+                    name: t.name.clone(),
+                    album: t.album.clone(),
+                    artists: t.artists.clone(),
+                })
+                .collect(),
+        };
+        Ok(items)
+    }
+
+    async fn tracks(
+        &self,
+        collection: &Collection,
+        // the library is indexed in memory, so there is nothing to refresh
+        _force_refresh: bool,
+    ) -> anyhow::Result<Vec<Track>> {
+        let tracks = match collection {
+            Collection::Playlist { id } => {
+                let playlist = self
+                    .playlists
+                    .iter()
+                    .find(|p| &p.id == id)
+                    .with_context(|| format!("playlist {id} not found"))?;
+                playlist
+                    .tracks
+                    .iter()
+                    .map(|&i| self.tracks[i].clone())
+                    .collect()
+            }
+            Collection::Album { id } => self
+                .tracks
+                .iter()
+                .zip(&self.album_ids)
+                .filter(|(_, album_id)| *album_id == id)
+                .map(|(t, _)| t.clone())
+                .collect::<Vec<_>>(),
+            Collection::Artist { id } => self
+                .tracks
+                .iter()
+                .filter(|t| t.artists.contains(id))
+                .cloned()
+                .collect::<Vec<_>>(),
+            Collection::LikedSongs | Collection::TopTracks { .. } | Collection::RecentlyPlayed => {
+                anyhow::bail!("{collection} are not available in the local library")
+            }
+            Collection::Imported { .. } => {
+                anyhow::bail!("{collection} is not stored in the local library")
+            }
+        };
+        if tracks.is_empty() {
+            anyhow::bail!("{collection} not found");
+        }
+        Ok(tracks)
+    }
+
+    async fn find_track(&self, query: &TrackQuery) -> anyhow::Result<Option<Track>> {
+        if let Some(track) = query.location.as_deref().and_then(|l| self.find_file(l)) {
+            return Ok(Some(track.clone()));
+        }
+
+        let Some(title) = &query.title else {
+            return Ok(None);
+        };
+        // only match titles if the entry has no artist
+        let artist = |artists: &[String]| match &query.artist {
+            Some(_) => artists.first().cloned().unwrap_or_default(),
+            None => String::new(),
+        };
+        let key = match_key(title, query.artist.as_deref().unwrap_or_default());
+        Ok(self
+            .tracks
+            .iter()
+            .find(|t| match_key(&t.name, &artist(&t.artists)) == key)
+            .cloned())
+    }
+
+    async fn snippet_audio(&self, snippet: &Snippet) -> anyhow::Result<SnippetAudio> {
+        match snippet {
+            Snippet::File(path) => Ok(SnippetAudio {
+                data: tokio::fs::read(path).await?,
+                content_type: None,
+            }),
+            // e.g. snippets of fallback resolvers or quiz packs
+            Snippet::Url(url) => audio::download(url).await,
+        }
+    }
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.contains(&ext.to_lowercase().as_str()))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_skips_symlink_cycles() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("Artist");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("list.m3u"), "").unwrap();
+        std::os::unix::fs::symlink(root.path(), dir.join("loop")).unwrap();
+
+        let mut library = Library::scan(root.path()).unwrap();
+        // the playlist is only found once, not once more per loop
+        let mut playlist_files = Vec::new();
+        let root = library.root.clone();
+        library
+            .scan_dir(&root, &mut playlist_files, &mut HashSet::new())
+            .unwrap();
+        assert_eq!(playlist_files, [root.join("Artist/list.m3u")]);
+    }
+}
@@ -1,13 +1,182 @@
-mod api;
-mod client;
-mod game;
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use song_guessr_server::{api, audio, cache, client, import, library, pack, resolver, source};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SourceKind {
+    /// Generate games from Spotify playlists
+    Spotify,
+    /// Generate games from a directory of audio files, see `--music-dir`
+    Local,
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Music source used to generate games
+    #[arg(long, value_enum, default_value_t = SourceKind::Spotify)]
+    source: SourceKind,
+
+    /// Directory of audio files (MP3, FLAC, Ogg, M4A) used by the local music source
+    #[arg(long, required_if_eq("source", "local"))]
+    music_dir: Option<PathBuf>,
+
+    /// Directory of audio files used for tracks that the music source has no snippet for,
+    /// matched by ISRC or by title and artist.
+    /// Also contains the audio files of quiz packs if the music source isn't local.
+    #[arg(long)]
+    fallback_music_dir: Option<PathBuf>,
+
+    /// Use Deezer's previews for tracks that the music source has no snippet for.
+    /// Games take longer to start when many tracks need to be looked up.
+    #[arg(long)]
+    deezer_previews: bool,
+
+    /// Directory to cache downloaded audio snippets and playlists,
+    /// and to store imported playlists and quiz packs
+    #[arg(long, default_value = "/tmp/song-guessr-cache")]
+    cache_dir: PathBuf,
+
+    /// Maximum size of the cached audio snippets in MiB,
+    /// the least recently used snippets are removed beyond it
+    #[arg(long, default_value_t = 1024)]
+    snippet_cache_size: u64,
+
+    /// Number of seconds a cached playlist is used before checking whether it has changed
+    #[arg(long, default_value_t = 3600)]
+    playlist_cache_ttl: u64,
+
+    #[command(flatten)]
+    spotify: SpotifyArgs,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Log in to Spotify through the browser and save the token to the token cache,
+    /// so that the server can later be started without user interaction
+    Auth,
+}
+
+#[derive(Debug, Args)]
+struct SpotifyArgs {
+    /// Spotify app's client ID
+    #[arg(long, global = true, env = "SPOTIFY_CLIENT_ID", default_value = client::SPOTIFY_CLIENT_ID)]
+    client_id: String,
+
+    /// File to cache the Spotify user's token
+    #[arg(long, global = true, default_value = "/tmp/spotify_token_cache.json")]
+    token_cache: PathBuf,
+
+    /// Spotify refresh token, used when the token cache is missing
+    #[arg(long, env = "SPOTIFY_REFRESH_TOKEN", hide_env_values = true)]
+    refresh_token: Option<String>,
+
+    /// File containing a Spotify refresh token, used when the token cache is missing
+    #[arg(long, conflicts_with = "refresh_token")]
+    refresh_token_file: Option<PathBuf>,
+
+    /// Authorize as the Spotify app instead of a user, which requires `--client-id` and
+    /// `--client-secret` and only gives access to public playlists
+    #[arg(long, requires = "client_secret")]
+    client_credentials: bool,
+
+    /// Spotify app's client secret, used in the client credentials mode
+    #[arg(long, env = "SPOTIFY_CLIENT_SECRET", hide_env_values = true)]
+    client_secret: Option<String>,
+}
+
+impl SpotifyArgs {
+    fn into_client(self, playlists: cache::PlaylistCache) -> anyhow::Result<client::Client> {
+        let auth = if self.client_credentials {
+            client::Auth::ClientCredentials {
+                client_secret: self.client_secret.unwrap_or_default(),
+            }
+        } else {
+            let refresh_token = match self.refresh_token_file {
+                Some(path) => Some(
+                    std::fs::read_to_string(&path)
+                        .with_context(|| format!("failed to read {}", path.display()))?
+                        .trim()
+                        .to_string(),
+                ),
+                None => self.refresh_token,
+            };
+            client::Auth::User {
+                token_cache: self.token_cache,
+                refresh_token,
+            }
+        };
+        Ok(client::Client::new(&self.client_id, auth, playlists))
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let mut client = client::Client::new();
-    client.get_token().await?;
+    let cli = Cli::parse();
+
+    if let Some(Command::Auth) = cli.command {
+        let playlists = cache::PlaylistCache::new(cli.cache_dir.join("playlists"), Duration::ZERO)?;
+        let mut client = cli.spotify.into_client(playlists)?;
+        client.login().await?;
+        println!("Spotify token is saved to the token cache");
+        return Ok(());
+    }
+
+    // library that the audio files of quiz packs are looked up in
+    let mut library = None;
+    let source: Arc<dyn source::MusicSource> = match cli.source {
+        SourceKind::Spotify => {
+            let playlists = cache::PlaylistCache::new(
+                cli.cache_dir.join("playlists"),
+                Duration::from_secs(cli.playlist_cache_ttl),
+            )?;
+            let client = Arc::new(cli.spotify.into_client(playlists)?);
+            client.authenticate().await?;
+            client.clone().spawn_token_refresh();
+            client
+        }
+        SourceKind::Local => {
+            let dir = cli.music_dir.expect("music directory should be provided");
+            let local = Arc::new(library::Library::scan(&dir)?);
+            library = Some(local.clone());
+            local
+        }
+    };
+
+    // entries of imported playlists are looked up in the music source first,
+    // then in the fallback library
+    let mut import_sources = vec![source.clone()];
+    let mut resolvers: Vec<Box<dyn resolver::SnippetResolver>> = Vec::new();
+    if let Some(dir) = &cli.fallback_music_dir {
+        let fallback = Arc::new(library::Library::scan(dir)?);
+        resolvers.push(Box::new(resolver::LibraryResolver::new(&fallback)));
+        import_sources.push(fallback.clone());
+        library.get_or_insert(fallback);
+    }
+    if cli.deezer_previews {
+        resolvers.push(Box::new(resolver::DeezerResolver::new()));
+    }
 
-    let app = api::new_app(client);
+    let snippets = audio::SnippetCache::new(
+        cli.cache_dir.join("snippets"),
+        cli.snippet_cache_size * 1024 * 1024,
+    )?;
+    let importer = import::Importer::new(cli.cache_dir.join("imports"), import_sources)?;
+    let packs = pack::PackStore::new(cli.cache_dir.join("packs"), library)?;
+    let app = api::new_app(
+        source,
+        snippets,
+        resolver::ResolverChain::new(resolvers),
+        importer,
+        packs,
+    );
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
     axum::serve(listener, app).await.unwrap();
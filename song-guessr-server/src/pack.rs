@@ -0,0 +1,548 @@
+use anyhow::Context;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::audio;
+use crate::game::{self, Question, QuestionType};
+use crate::library::Library;
+use crate::scoring::{ScoringRules, SCORE_LIMIT};
+use crate::source::Snippet;
+use crate::store::RecordDir;
+
+const DEFAULT_PACK_NAME: &str = "Quiz pack";
+// length of the secret token required to delete a pack
+const OWNER_TOKEN_LEN: usize = 32;
+// maximum number of audio URLs of a pack checked at the same time
+const MAX_CONCURRENT_PROBES: usize = 8;
+
+/// Format of a quiz pack file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Toml,
+}
+
+impl Format {
+    /// Guess the format of a quiz pack file from its content
+    fn detect(content: &str) -> Self {
+        match content.trim_start().chars().next() {
+            Some('{') => Self::Json,
+            _ => Self::Toml,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Toml => "toml",
+        }
+    }
+}
+
+/// A fixed set of hand-written questions that games can be started from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuizPack {
+    #[serde(default = "default_pack_name")]
+    pub name: String,
+    pub questions: Vec<PackQuestion>,
+}
+
+fn default_pack_name() -> String {
+    DEFAULT_PACK_NAME.to_string()
+}
+
+/// A question of a quiz pack
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackQuestion {
+    pub question_type: QuestionType,
+    pub choices: Vec<String>,
+    // index of the correct choice
+    pub answer: usize,
+    pub audio: Audio,
+    // score of a correct answer, defaults to the score of a generated question at the same position
+    // by the game's scoring rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<u64>,
+    // give part of the score to choices close to the answer, for year and decade questions
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial_credit: bool,
+}
+
+/// Audio played during a quiz pack question
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Audio {
+    /// HTTP(S) URL of an audio file, such as an MP3 or Ogg file
+    Url(String),
+    /// Audio file of the server's local library, relative to the library's root
+    File(String),
+}
+
+/// Parse a quiz pack file, detecting its format from the content if not given
+pub fn parse(content: &str, format: Option<Format>) -> anyhow::Result<QuizPack> {
+    match format.unwrap_or_else(|| Format::detect(content)) {
+        Format::Json => serde_json::from_str(content).context("invalid JSON quiz pack"),
+        Format::Toml => toml::from_str(content).context("invalid TOML quiz pack"),
+    }
+}
+
+/// Serialize a quiz pack to a file of the given format
+pub fn serialize(pack: &QuizPack, format: Format) -> anyhow::Result<String> {
+    Ok(match format {
+        Format::Json => serde_json::to_string_pretty(pack)?,
+        Format::Toml => toml::to_string_pretty(pack)?,
+    })
+}
+
+/// A quiz pack stored by the server
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredPack {
+    pub id: String,
+    // secret given to the uploader of the pack, required to delete it.
+    // Packs stored without one can't be deleted through the API.
+    #[serde(default)]
+    pub owner_token: String,
+    #[serde(flatten)]
+    pub pack: QuizPack,
+}
+
+/// Summary of a stored quiz pack
+#[derive(Debug, Serialize)]
+pub struct PackInfo {
+    pub id: String,
+    pub name: String,
+    pub num_questions: usize,
+}
+
+/// Why a quiz pack can't be played or changed
+#[derive(Debug, thiserror::Error)]
+pub enum PackError {
+    #[error("quiz pack {name} has no questions")]
+    NoQuestions { name: String },
+    #[error("invalid quiz pack {name}: {}", problems.join("; "))]
+    Invalid { name: String, problems: Vec<String> },
+    #[error("only the uploader of quiz pack {id} can delete it")]
+    NotUploader { id: String },
+}
+
+/// Validates quiz packs and stores them on disk
+pub struct PackStore {
+    records: RecordDir,
+    // library that the packs' audio files are looked up in
+    library: Option<Arc<Library>>,
+}
+
+impl PackStore {
+    pub fn new(dir: PathBuf, library: Option<Arc<Library>>) -> anyhow::Result<Self> {
+        let records = RecordDir::new(dir, "quiz pack")?;
+        Ok(Self { records, library })
+    }
+
+    /// Validate a quiz pack and store it as a new pack
+    pub async fn add(&self, pack: QuizPack) -> anyhow::Result<StoredPack> {
+        self.questions(&pack, &ScoringRules::default())?;
+        let pack = StoredPack {
+            id: game::gen_id(8),
+            owner_token: game::gen_id(OWNER_TOKEN_LEN),
+            pack,
+        };
+        self.records.write(&pack.id, &pack).await?;
+        Ok(pack)
+    }
+
+    /// Validate a quiz pack, then check that the audio of its URLs can be downloaded,
+    /// so that packs with broken links are rejected when uploaded instead of during games
+    pub async fn check_audio(&self, pack: &QuizPack) -> anyhow::Result<()> {
+        self.questions(pack, &ScoringRules::default())?;
+        let urls = pack
+            .questions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, question)| match &question.audio {
+                Audio::Url(url) => Some((i, url.clone())),
+                Audio::File(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let problems = futures::stream::iter(urls)
+            .map(|(i, url)| async move {
+                let err = audio::probe(&url).await.err()?;
+                Some(format!("question {}: {err:#}", i + 1))
+            })
+            .buffered(MAX_CONCURRENT_PROBES)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        if !problems.is_empty() {
+            let name = pack.name.clone();
+            return Err(PackError::Invalid { name, problems }.into());
+        }
+        Ok(())
+    }
+
+    pub async fn get(&self, id: &str) -> anyhow::Result<StoredPack> {
+        self.records.read(id).await
+    }
+
+    pub async fn list(&self) -> anyhow::Result<Vec<PackInfo>> {
+        let stored = self.records.list::<StoredPack>().await?;
+        let mut packs = stored
+            .into_iter()
+            .map(|stored| PackInfo {
+                id: stored.id,
+                name: stored.pack.name,
+                num_questions: stored.pack.questions.len(),
+            })
+            .collect::<Vec<_>>();
+        packs.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(packs)
+    }
+
+    /// Delete a pack, given the owner token returned when it was added
+    pub async fn delete(&self, id: &str, owner_token: &str) -> anyhow::Result<()> {
+        let stored = self.get(id).await?;
+        if stored.owner_token.is_empty() || stored.owner_token != owner_token {
+            return Err(PackError::NotUploader { id: id.to_string() }.into());
+        }
+        self.records.remove(id).await
+    }
+
+    /// Validate a quiz pack and convert it to game questions.
+    /// All problems of the pack are reported at once so that they can be fixed together.
+    /// Questions without a score are scored by the given rules.
+    pub fn questions(
+        &self,
+        pack: &QuizPack,
+        rules: &ScoringRules,
+    ) -> anyhow::Result<Vec<Question>> {
+        if pack.questions.is_empty() {
+            let name = pack.name.clone();
+            return Err(PackError::NoQuestions { name }.into());
+        }
+
+        let mut questions = Vec::new();
+        let mut problems = Vec::new();
+        for (i, question) in pack.questions.iter().enumerate() {
+            match self.question(i, question, rules) {
+                Ok(question) => questions.push(question),
+                Err(err) => problems.push(format!("question {}: {err}", i + 1)),
+            }
+        }
+        if !problems.is_empty() {
+            let name = pack.name.clone();
+            return Err(PackError::Invalid { name, problems }.into());
+        }
+        Ok(questions)
+    }
+
+    fn question(
+        &self,
+        index: usize,
+        question: &PackQuestion,
+        rules: &ScoringRules,
+    ) -> anyhow::Result<Question> {
+        if question.choices.len() < game::MIN_CHOICES {
+            anyhow::bail!("at least {} choices are required", game::MIN_CHOICES);
+        }
+        if question.choices.len() > game::MAX_CHOICES {
+            anyhow::bail!("at most {} choices are allowed", game::MAX_CHOICES);
+        }
+        if question.choices.iter().any(|c| c.trim().is_empty()) {
+            anyhow::bail!("choices must not be empty");
+        }
+        // identical choices would make more than one choice correct
+        let mut seen = HashSet::new();
+        if !question
+            .choices
+            .iter()
+            .all(|c| seen.insert(c.trim().to_lowercase()))
+        {
+            anyhow::bail!("choices must be distinct");
+        }
+        if question.answer >= question.choices.len() {
+            anyhow::bail!(
+                "answer {} is out of range, there are {} choices",
+                question.answer,
+                question.choices.len()
+            );
+        }
+
+        let score = question
+            .score
+            .unwrap_or_else(|| rules.question_score(index));
+        if score == 0 || score > SCORE_LIMIT {
+            anyhow::bail!("score must be between 1 and {}", SCORE_LIMIT);
+        }
+
+        let question = Question {
+            question_type: question.question_type,
+            choices: question.choices.clone(),
+            snippet: self.snippet(&question.audio)?,
+            score,
+            bonus: rules.bonus(score),
+            ans_id: question.answer,
+            partial_credit: question.partial_credit,
+            // the answer mode is chosen when a game is started from the pack
+            answer_mode: game::AnswerMode::default(),
+            // the answer's artists aren't known apart from the answer itself
+            answer_artists: Vec::new(),
+        };
+        if question.partial_credit
+            && (0..question.choices.len()).any(|id| question.choice_distance(id).is_none())
+        {
+            anyhow::bail!(
+                "partial credit requires a year or decade question with choices such as 1994 or 1990s"
+            );
+        }
+        Ok(question)
+    }
+
+    /// Convert the questions of a game to a quiz pack, so that the game can be replayed later
+    pub fn pack(&self, name: String, questions: &[Question]) -> anyhow::Result<QuizPack> {
+        let questions = questions
+            .iter()
+            .map(|question| {
+                Ok(PackQuestion {
+                    question_type: question.question_type,
+                    choices: question.choices.clone(),
+                    answer: question.ans_id,
+                    audio: self.audio(&question.snippet)?,
+                    score: Some(question.score),
+                    partial_credit: question.partial_credit,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(QuizPack { name, questions })
+    }
+
+    /// Get the audio of a question's snippet, the inverse of [`Self::snippet`]
+    fn audio(&self, snippet: &Snippet) -> anyhow::Result<Audio> {
+        match snippet {
+            Snippet::Url(url) => Ok(Audio::Url(url.clone())),
+            Snippet::File(path) => self
+                .library
+                .as_ref()
+                .and_then(|library| library.relative_location(path))
+                .map(Audio::File)
+                .with_context(|| {
+                    format!("audio file {} is not in the local library", path.display())
+                }),
+        }
+    }
+
+    /// Get the snippet of a question's audio, checking that the audio can be played
+    fn snippet(&self, audio: &Audio) -> anyhow::Result<Snippet> {
+        match audio {
+            // the URL is checked again when the audio is downloaded, as host names may
+            // resolve to other addresses by then
+            Audio::Url(url) => {
+                let parsed =
+                    reqwest::Url::parse(url).with_context(|| format!("invalid URL {url}"))?;
+                audio::check_url(&parsed)?;
+                Ok(Snippet::Url(url.clone()))
+            }
+            Audio::File(path) => {
+                let library = self
+                    .library
+                    .as_ref()
+                    .context("audio files require a local music directory on the server")?;
+                library
+                    .find_file(path)
+                    .and_then(|track| track.snippet.clone())
+                    .with_context(|| format!("audio file {path} not found in the local library"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store in a temporary directory, which is deleted with it
+    fn store() -> (PackStore, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        (PackStore::new(dir.path().to_path_buf(), None).unwrap(), dir)
+    }
+
+    fn question(choices: &[&str], answer: usize) -> PackQuestion {
+        PackQuestion {
+            question_type: QuestionType::Song,
+            choices: choices.iter().map(|c| c.to_string()).collect(),
+            answer,
+            audio: Audio::Url("https://example.com/a.mp3".to_string()),
+            score: None,
+            partial_credit: false,
+        }
+    }
+
+    fn pack(questions: Vec<PackQuestion>) -> QuizPack {
+        QuizPack {
+            name: "Test".to_string(),
+            questions,
+        }
+    }
+
+    fn error(store: &PackStore, questions: Vec<PackQuestion>) -> String {
+        store
+            .questions(&pack(questions), &ScoringRules::default())
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn questions_of_valid_pack() {
+        let (store, _dir) = store();
+        let mut scored = question(&["a", "b", "c"], 2);
+        scored.score = Some(1234);
+        let questions = store
+            .questions(
+                &pack(vec![question(&["a", "b"], 0), scored]),
+                &ScoringRules::default(),
+            )
+            .unwrap();
+        assert_eq!(questions.len(), 2);
+        // unscored questions are scored like generated questions
+        assert_eq!(questions[0].score, 500);
+        assert_eq!(questions[1].score, 1234);
+        assert_eq!(questions[1].ans_id, 2);
+        assert_eq!(
+            questions[1].snippet,
+            Snippet::Url("https://example.com/a.mp3".to_string())
+        );
+    }
+
+    #[test]
+    fn questions_reports_all_problems() {
+        let (store, _dir) = store();
+        assert!(error(&store, vec![]).contains("has no questions"));
+
+        let err = error(
+            &store,
+            vec![
+                question(&["a"], 0),
+                question(&["a", "b"], 0),
+                question(&["a", "A "], 0),
+                question(&["a", "b"], 2),
+                question(&["a", " "], 0),
+            ],
+        );
+        assert!(
+            err.contains("question 1: at least 2 choices are required"),
+            "{err}"
+        );
+        assert!(!err.contains("question 2"), "{err}");
+        assert!(
+            err.contains("question 3: choices must be distinct"),
+            "{err}"
+        );
+        assert!(
+            err.contains("question 4: answer 2 is out of range"),
+            "{err}"
+        );
+        assert!(
+            err.contains("question 5: choices must not be empty"),
+            "{err}"
+        );
+
+        let many = (0..=game::MAX_CHOICES)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>();
+        let many = many.iter().map(String::as_str).collect::<Vec<_>>();
+        assert!(error(&store, vec![question(&many, 0)]).contains("at most 8 choices are allowed"));
+    }
+
+    #[test]
+    fn questions_checks_scores_and_partial_credit() {
+        let (store, _dir) = store();
+        let mut unscored = question(&["a", "b"], 0);
+        unscored.score = Some(0);
+        assert!(error(&store, vec![unscored]).contains("score must be between"));
+
+        let mut partial = question(&["a", "b"], 0);
+        partial.partial_credit = true;
+        assert!(error(&store, vec![partial]).contains("partial credit requires"));
+
+        let mut years = question(&["1994", "1996"], 0);
+        years.question_type = QuestionType::Year;
+        years.partial_credit = true;
+        assert!(store
+            .questions(&pack(vec![years]), &ScoringRules::default())
+            .is_ok());
+    }
+
+    #[test]
+    fn questions_checks_audio() {
+        let (store, _dir) = store();
+        let mut local = question(&["a", "b"], 0);
+        local.audio = Audio::Url("file:///etc/passwd".to_string());
+        assert!(error(&store, vec![local]).contains("not an HTTP(S) URL"));
+
+        let mut private = question(&["a", "b"], 0);
+        private.audio = Audio::Url("http://127.0.0.1/a.mp3".to_string());
+        assert!(error(&store, vec![private]).contains("non-public address"));
+
+        let mut file = question(&["a", "b"], 0);
+        file.audio = Audio::File("a.mp3".to_string());
+        assert!(error(&store, vec![file]).contains("require a local music directory"));
+    }
+
+    #[test]
+    fn pack_of_game_questions() {
+        let (store, _dir) = store();
+        let mut scored = question(&["a", "b", "c"], 1);
+        scored.score = Some(1234);
+        let questions = store
+            .questions(
+                &pack(vec![question(&["a", "b"], 0), scored]),
+                &ScoringRules::default(),
+            )
+            .unwrap();
+
+        // the exported pack has the same questions, with their scores
+        let exported = store.pack("Replay".to_string(), &questions).unwrap();
+        assert_eq!(exported.name, "Replay");
+        let replayed = store
+            .questions(&exported, &ScoringRules::default())
+            .unwrap();
+        for (question, replayed) in questions.iter().zip(&replayed) {
+            assert_eq!(question.choices, replayed.choices);
+            assert_eq!(question.ans_id, replayed.ans_id);
+            assert_eq!(question.score, replayed.score);
+            assert_eq!(question.snippet, replayed.snippet);
+        }
+
+        let local = Question {
+            snippet: Snippet::File("/music/a.mp3".into()),
+            ..questions[0].clone()
+        };
+        assert!(store.pack("Local".to_string(), &[local]).is_err());
+    }
+
+    #[tokio::test]
+    async fn add_and_delete_packs() {
+        let (store, _dir) = store();
+        let stored = store
+            .add(pack(vec![question(&["a", "b"], 0)]))
+            .await
+            .unwrap();
+        let packs = store.list().await.unwrap();
+        assert_eq!(packs.len(), 1);
+        assert_eq!(packs[0].id, stored.id);
+        assert_eq!(store.get(&stored.id).await.unwrap().pack.name, "Test");
+
+        assert!(store.get("../secret").await.is_err());
+        // only the uploader's token deletes the pack
+        assert!(store.delete(&stored.id, "wrong").await.is_err());
+        assert!(store.delete(&stored.id, "").await.is_err());
+        store.delete(&stored.id, &stored.owner_token).await.unwrap();
+        assert!(store.list().await.unwrap().is_empty());
+        assert!(store.delete(&stored.id, &stored.owner_token).await.is_err());
+    }
+}
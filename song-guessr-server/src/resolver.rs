@@ -0,0 +1,391 @@
+use anyhow::bail;
+use dashmap::DashMap;
+use futures::StreamExt;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use crate::library::Library;
+use crate::source::{Snippet, Track};
+use crate::title;
+
+// maximum number of tracks resolved at the same time
+const MAX_CONCURRENT_RESOLVES: usize = 4;
+const DEEZER_API_URL: &str = "https://api.deezer.com";
+// an unreachable provider fails the track's resolution instead of delaying the game's start
+const DEEZER_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEEZER_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// code of Deezer's errors for lookups that match nothing, e.g. an unknown ISRC
+const DEEZER_NO_DATA: i64 = 800;
+
+/// A fallback source of audio snippets for tracks that their music source has no snippet for
+#[async_trait::async_trait]
+pub trait SnippetResolver: Send + Sync {
+    /// Name of the resolver, used in snippet reports
+    fn name(&self) -> &'static str;
+
+    /// Find a snippet of the track, returning `None` if the resolver has no snippet for it
+    async fn resolve(&self, track: &Track) -> anyhow::Result<Option<Snippet>>;
+}
+
+/// Why a track can't be used in a game
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnusableReason {
+    /// Neither the music source nor any fallback resolver has a snippet of the track
+    NoSnippet,
+    /// A fallback resolver failed, e.g. because its provider is unreachable
+    ResolverFailed,
+}
+
+/// Summary of the snippet resolution of a game's tracks
+#[derive(Debug, Default, Serialize)]
+pub struct SnippetReport {
+    pub total: usize,
+    // number of tracks whose snippets are found by fallback resolvers, by resolver name
+    pub resolved: BTreeMap<&'static str, usize>,
+    // number of tracks without a usable snippet, by reason
+    pub unusable: BTreeMap<UnusableReason, usize>,
+}
+
+enum Resolution {
+    // the track's snippet is provided by its music source
+    Source,
+    Resolved(&'static str),
+    Unusable(UnusableReason),
+}
+
+/// Fallback resolvers, tried in order for every track without a snippet
+#[derive(Default)]
+pub struct ResolverChain {
+    resolvers: Vec<Box<dyn SnippetResolver>>,
+    // resolutions of tracks by ISRC or ID, so that tracks played again, e.g. by every
+    // game of the same playlist, aren't looked up again. Failures aren't cached to be retried.
+    resolved: DashMap<String, Result<(Snippet, &'static str), UnusableReason>>,
+}
+
+impl ResolverChain {
+    pub fn new(resolvers: Vec<Box<dyn SnippetResolver>>) -> Self {
+        Self {
+            resolvers,
+            resolved: DashMap::new(),
+        }
+    }
+
+    /// Fill in the missing snippets of the given tracks,
+    /// dropping the tracks that still don't have a snippet afterwards
+    pub async fn resolve(&self, tracks: Vec<Track>) -> (Vec<Track>, SnippetReport) {
+        let mut report = SnippetReport {
+            total: tracks.len(),
+            ..Default::default()
+        };
+
+        let results = futures::stream::iter(tracks)
+            .map(|mut track| async move {
+                if track.snippet.is_some() {
+                    return (track, Resolution::Source);
+                }
+                let resolution = match self.resolve_track(&track).await {
+                    Ok((snippet, name)) => {
+                        track.snippet = Some(snippet);
+                        Resolution::Resolved(name)
+                    }
+                    Err(reason) => Resolution::Unusable(reason),
+                };
+                (track, resolution)
+            })
+            .buffered(MAX_CONCURRENT_RESOLVES)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut tracks = Vec::new();
+        for (track, resolution) in results {
+            match resolution {
+                Resolution::Source => tracks.push(track),
+                Resolution::Resolved(name) => {
+                    *report.resolved.entry(name).or_default() += 1;
+                    tracks.push(track);
+                }
+                Resolution::Unusable(reason) => *report.unusable.entry(reason).or_default() += 1,
+            }
+        }
+        (tracks, report)
+    }
+
+    async fn resolve_track(
+        &self,
+        track: &Track,
+    ) -> Result<(Snippet, &'static str), UnusableReason> {
+        let key = match &track.isrc {
+            Some(isrc) => format!("isrc:{}", isrc.to_uppercase()),
+            None => format!("id:{}", track.id),
+        };
+        if let Some(resolution) = self.resolved.get(&key) {
+            return resolution.clone();
+        }
+        let resolution = self.try_resolvers(track).await;
+        if resolution != Err(UnusableReason::ResolverFailed) {
+            self.resolved.insert(key, resolution.clone());
+        }
+        resolution
+    }
+
+    async fn try_resolvers(
+        &self,
+        track: &Track,
+    ) -> Result<(Snippet, &'static str), UnusableReason> {
+        let mut failed = false;
+        for resolver in &self.resolvers {
+            match resolver.resolve(track).await {
+                Ok(Some(snippet)) => return Ok((snippet, resolver.name())),
+                Ok(None) => {}
+                // keep trying the remaining resolvers
+                Err(_) => failed = true,
+            }
+        }
+        Err(if failed {
+            UnusableReason::ResolverFailed
+        } else {
+            UnusableReason::NoSnippet
+        })
+    }
+}
+
+/// Resolves snippets from a local library's audio files,
+/// matching tracks by ISRC or by their normalized title and artist
+pub struct LibraryResolver {
+    by_isrc: HashMap<String, Snippet>,
+    by_name: HashMap<String, Snippet>,
+}
+
+impl LibraryResolver {
+    pub fn new(library: &Library) -> Self {
+        let mut by_isrc = HashMap::new();
+        let mut by_name = HashMap::new();
+        for track in library.tracks() {
+            let Some(snippet) = &track.snippet else {
+                continue;
+            };
+            if let Some(isrc) = &track.isrc {
+                by_isrc.insert(isrc.to_uppercase(), snippet.clone());
+            }
+            by_name.insert(track_key(track), snippet.clone());
+        }
+        Self { by_isrc, by_name }
+    }
+}
+
+#[async_trait::async_trait]
+impl SnippetResolver for LibraryResolver {
+    fn name(&self) -> &'static str {
+        "library"
+    }
+
+    async fn resolve(&self, track: &Track) -> anyhow::Result<Option<Snippet>> {
+        let snippet = track
+            .isrc
+            .as_ref()
+            .and_then(|isrc| self.by_isrc.get(&isrc.to_uppercase()))
+            .or_else(|| self.by_name.get(&track_key(track)));
+        Ok(snippet.cloned())
+    }
+}
+
+/// Resolves snippets from Deezer's 30-second track previews, which don't require authentication
+pub struct DeezerResolver {
+    http: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeezerTrack {
+    // empty if the track has no preview
+    #[serde(default)]
+    preview: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeezerSearchResult {
+    #[serde(default)]
+    data: Vec<DeezerTrack>,
+}
+
+// Deezer reports errors, such as exceeded quotas, with a successful status and an error body
+#[derive(Debug, Deserialize)]
+struct DeezerResponse<T> {
+    error: Option<DeezerError>,
+    #[serde(flatten)]
+    data: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeezerError {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    code: i64,
+}
+
+impl DeezerResolver {
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .connect_timeout(DEEZER_CONNECT_TIMEOUT)
+                .timeout(DEEZER_REQUEST_TIMEOUT)
+                .build()
+                .expect("Deezer HTTP client should be valid"),
+        }
+    }
+
+    /// Send a request to Deezer's API, returning `None` if the request matches nothing
+    async fn get<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> anyhow::Result<Option<T>> {
+        let response: DeezerResponse<T> = request.send().await?.error_for_status()?.json().await?;
+        match response.error {
+            None => Ok(Some(response.data)),
+            Some(err) if err.code == DEEZER_NO_DATA => Ok(None),
+            Some(err) => bail!("Deezer error {} ({}): {}", err.code, err.kind, err.message),
+        }
+    }
+}
+
+impl Default for DeezerResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl SnippetResolver for DeezerResolver {
+    fn name(&self) -> &'static str {
+        "deezer"
+    }
+
+    async fn resolve(&self, track: &Track) -> anyhow::Result<Option<Snippet>> {
+        if let Some(isrc) = &track.isrc {
+            let found: Option<DeezerTrack> = self
+                .get(self.http.get(format!("{DEEZER_API_URL}/track/isrc:{isrc}")))
+                .await?;
+            if let Some(found) = found.filter(|t| !t.preview.is_empty()) {
+                return Ok(Some(Snippet::Url(found.preview)));
+            }
+        }
+
+        let artist = track
+            .artists
+            .first()
+            .map(String::as_str)
+            .unwrap_or_default();
+        let query = format!("artist:\"{artist}\" track:\"{}\"", track.name);
+        let result: Option<DeezerSearchResult> = self
+            .get(
+                self.http
+                    .get(format!("{DEEZER_API_URL}/search"))
+                    .query(&[("q", query.as_str()), ("limit", "1")]),
+            )
+            .await?;
+        Ok(result
+            .into_iter()
+            .flat_map(|result| result.data)
+            .map(|t| t.preview)
+            .find(|preview| !preview.is_empty())
+            .map(Snippet::Url))
+    }
+}
+
+/// Key used to match the same recording across music sources, based on its title and
+/// main artist, ignoring case, accents, punctuation and version details such as
+/// "- Remastered" or "(feat. X)", see [`title::key`]
+pub fn match_key(title: &str, artist: &str) -> String {
+    format!("{}\u{0}{}", title::key(title), title::fold(artist))
+}
+
+fn track_key(track: &Track) -> String {
+    let artist = track
+        .artists
+        .first()
+        .map(String::as_str)
+        .unwrap_or_default();
+    match_key(&track.name, artist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // resolves the snippets of tracks with an ISRC, failing for the others
+    struct CountingResolver(Arc<AtomicUsize>);
+
+    #[async_trait::async_trait]
+    impl SnippetResolver for CountingResolver {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        async fn resolve(&self, track: &Track) -> anyhow::Result<Option<Snippet>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            match &track.isrc {
+                Some(isrc) => Ok(resolved_snippet(isrc)),
+                None => bail!("unreachable"),
+            }
+        }
+    }
+
+    fn resolved_snippet(isrc: &str) -> Option<Snippet> {
+        Some(Snippet::Url(format!("https://example.com/{isrc}.mp3")))
+    }
+
+    fn track(id: &str, isrc: Option<&str>) -> Track {
+        Track {
+            id: id.into(),
+            name: format!("Song {id}"),
+            album: "Album".into(),
+            artists: vec!["Artist".into()],
+            year: None,
+            popularity: 50,
+            isrc: isrc.map(String::from),
+            snippet: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn resolutions_are_cached_but_failures_are_retried() {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let chain = ResolverChain::new(vec![Box::new(CountingResolver(lookups.clone()))]);
+        let (resolved, report) = chain
+            .resolve(vec![track("1", Some("usabc")), track("2", None)])
+            .await;
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(report.unusable[&UnusableReason::ResolverFailed], 1);
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+
+        // a track with the same ISRC isn't looked up again, unlike the failed track
+        let (resolved, _) = chain
+            .resolve(vec![track("3", Some("USABC")), track("2", None)])
+            .await;
+        assert_eq!(resolved[0].snippet, resolved_snippet("usabc"));
+        assert_eq!(lookups.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn deezer_errors_are_parsed() {
+        let response: DeezerResponse<DeezerTrack> = serde_json::from_str(
+            r#"{"error":{"type":"Exception","message":"Quota limit exceeded","code":4}}"#,
+        )
+        .unwrap();
+        let err = response.error.unwrap();
+        assert_eq!((err.kind.as_str(), err.code), ("Exception", 4));
+        assert_eq!(err.message, "Quota limit exceeded");
+
+        let response: DeezerResponse<DeezerTrack> =
+            serde_json::from_str(r#"{"id":3135556,"preview":"https://example.com/1.mp3"}"#)
+                .unwrap();
+        assert!(response.error.is_none());
+        assert_eq!(response.data.preview, "https://example.com/1.mp3");
+    }
+}
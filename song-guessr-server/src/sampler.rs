@@ -0,0 +1,126 @@
+use rand::Rng;
+
+/// Samples indices with probability proportional to their weights.
+///
+/// Weights are stored in a Fenwick tree, so that both sampling and updating a weight
+/// take O(log n) time.
+#[derive(Debug, Clone)]
+pub struct WeightedSampler {
+    // `tree[i]` is the sum of the weights of the indices in `[i - lowbit(i), i)`,
+    // `tree[0]` is unused
+    tree: Vec<u64>,
+    weights: Vec<u64>,
+}
+
+impl WeightedSampler {
+    pub fn new(weights: Vec<u64>) -> Self {
+        let mut tree = vec![0; weights.len() + 1];
+        // build the tree in O(n) by adding each node's sum to its parent
+        for i in 1..tree.len() {
+            tree[i] += weights[i - 1];
+            let parent = i + lowbit(i);
+            if parent < tree.len() {
+                tree[parent] += tree[i];
+            }
+        }
+        Self { tree, weights }
+    }
+
+    /// Sum of all weights
+    pub fn total(&self) -> u64 {
+        let mut total = 0;
+        let mut i = self.weights.len();
+        while i > 0 {
+            total += self.tree[i];
+            i -= lowbit(i);
+        }
+        total
+    }
+
+    pub fn weight(&self, index: usize) -> u64 {
+        self.weights[index]
+    }
+
+    pub fn set(&mut self, index: usize, weight: u64) {
+        let old = std::mem::replace(&mut self.weights[index], weight);
+        let mut i = index + 1;
+        while i < self.tree.len() {
+            // wrapping arithmetic adds the (possibly negative) difference of the weights
+            self.tree[i] = self.tree[i].wrapping_add(weight).wrapping_sub(old);
+            i += lowbit(i);
+        }
+    }
+
+    /// Sample an index with probability proportional to its weight,
+    /// returning `None` if all weights are zero
+    pub fn sample(&self, rng: &mut impl Rng) -> Option<usize> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+        // find the first index whose prefix sum of weights exceeds the target
+        let mut target = rng.gen_range(0..total);
+        let mut pos = 0;
+        let mut step = self.weights.len().next_power_of_two();
+        while step > 0 {
+            let next = pos + step;
+            if next < self.tree.len() && self.tree[next] <= target {
+                target -= self.tree[next];
+                pos = next;
+            }
+            step /= 2;
+        }
+        Some(pos)
+    }
+}
+
+fn lowbit(i: usize) -> usize {
+    i & i.wrapping_neg()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn total_and_set() {
+        let mut sampler = WeightedSampler::new(vec![1, 2, 3, 4, 5]);
+        assert_eq!(sampler.total(), 15);
+        sampler.set(2, 10);
+        assert_eq!(sampler.weight(2), 10);
+        assert_eq!(sampler.total(), 22);
+        sampler.set(4, 0);
+        assert_eq!(sampler.total(), 17);
+    }
+
+    #[test]
+    fn sample_skips_zero_weights() {
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(WeightedSampler::new(vec![]).sample(&mut rng), None);
+        assert_eq!(WeightedSampler::new(vec![0, 0]).sample(&mut rng), None);
+
+        let mut sampler = WeightedSampler::new(vec![0, 3, 0, 0, 1, 0]);
+        for _ in 0..100 {
+            assert!(matches!(sampler.sample(&mut rng), Some(1 | 4)));
+        }
+        sampler.set(1, 0);
+        for _ in 0..100 {
+            assert_eq!(sampler.sample(&mut rng), Some(4));
+        }
+    }
+
+    #[test]
+    fn sample_is_proportional_to_weights() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let sampler = WeightedSampler::new(vec![1, 0, 3, 6, 0, 0, 0]);
+        let mut counts = [0u32; 7];
+        for _ in 0..10_000 {
+            counts[sampler.sample(&mut rng).unwrap()] += 1;
+        }
+        assert_eq!(counts[1] + counts[4] + counts[5] + counts[6], 0);
+        for (index, expected) in [(0, 1000), (2, 3000), (3, 6000)] {
+            assert!(counts[index].abs_diff(expected) < 300, "{counts:?}");
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Scoring rules of games, chosen from presets or given by the game's host
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{check_range, SettingsError};
+
+/// Maximum score of a correct answer to a question, not counting the fastest answer's bonus
+pub const SCORE_LIMIT: u64 = 2000;
+
+/// How the answers of a game are scored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "preset", rename_all = "snake_case")]
+pub enum ScoringPolicy {
+    /// Every question is worth the same
+    Flat,
+    /// Questions are worth more as the game goes on
+    #[default]
+    Escalating,
+    /// Fast answers are worth much more than slow ones
+    SpeedHeavy,
+    /// Correct answers are worth the same however long they take
+    AccuracyOnly,
+    Custom(ScoringRules),
+}
+
+impl ScoringPolicy {
+    pub fn rules(&self) -> ScoringRules {
+        match self {
+            Self::Flat => ScoringRules {
+                base_score: 1000,
+                score_step: 0,
+                max_score: 1000,
+                ..ScoringRules::default()
+            },
+            Self::Escalating => ScoringRules::default(),
+            Self::SpeedHeavy => ScoringRules {
+                slowest_percent: 10,
+                fastest_bonus_percent: 50,
+                ..ScoringRules::default()
+            },
+            Self::AccuracyOnly => ScoringRules {
+                base_score: 1000,
+                score_step: 0,
+                max_score: 1000,
+                slowest_percent: 100,
+                fastest_bonus_percent: 0,
+            },
+            Self::Custom(rules) => *rules,
+        }
+    }
+
+    /// Check that custom rules are within the supported ranges
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let rules = self.rules();
+        check_range("base score", rules.base_score, (1, SCORE_LIMIT), "")?;
+        check_range(
+            "maximum score",
+            rules.max_score,
+            (rules.base_score, SCORE_LIMIT),
+            "",
+        )?;
+        check_range("score step", rules.score_step, (0, SCORE_LIMIT), "")?;
+        check_range("slowest score", rules.slowest_percent, (0, 100), "%")?;
+        check_range("fastest bonus", rules.fastest_bonus_percent, (0, 100), "%")
+    }
+}
+
+/// Parameters of a scoring policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ScoringRules {
+    // score of the first question
+    pub base_score: u64,
+    // increase of the score with each question, up to `max_score`
+    pub score_step: u64,
+    pub max_score: u64,
+    // percentage of the score kept by answers submitted at the end of the answer time,
+    // the score decreases linearly to it from the start of the song
+    pub slowest_percent: u64,
+    // percentage of the score given as a bonus to the fastest correct answer
+    pub fastest_bonus_percent: u64,
+}
+
+impl Default for ScoringRules {
+    fn default() -> Self {
+        Self {
+            base_score: 500,
+            score_step: 100,
+            max_score: SCORE_LIMIT,
+            slowest_percent: 50,
+            fastest_bonus_percent: 20,
+        }
+    }
+}
+
+impl ScoringRules {
+    /// Score of a correct answer to the question at the given position of a game
+    pub fn question_score(&self, index: usize) -> u64 {
+        (self.base_score + self.score_step * index as u64).min(self.max_score)
+    }
+
+    /// Bonus of the fastest correct answer to a question with the given score
+    pub fn bonus(&self, score: u64) -> u64 {
+        score * self.fastest_bonus_percent / 100
+    }
+
+    /// Score of an answer submitted after the given time, out of the answer time
+    pub fn timed_score(&self, score: u64, submitted_at_ms: u64, answer_time_ms: u64) -> u64 {
+        let decay = score * (100 - self.slowest_percent) / 100;
+        score - decay * submitted_at_ms.min(answer_time_ms) / answer_time_ms
+    }
+}
+
+/// Extra score of correct answers continuing a streak of correct answers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StreakBonus {
+    #[default]
+    None,
+    /// The score is increased by `step_percent` for each correct answer in the streak,
+    /// by at most `max_percent`
+    Multiplier { step_percent: u64, max_percent: u64 },
+    /// `step` points are added for each correct answer in the streak, at most `max`
+    Points { step: u64, max: u64 },
+}
+
+impl StreakBonus {
+    /// Score of a correct answer after a streak of the given number of correct answers
+    pub fn apply(&self, score: u64, streak: u32) -> u64 {
+        let streak = streak as u64;
+        match *self {
+            Self::None => score,
+            Self::Multiplier {
+                step_percent,
+                max_percent,
+            } => score + score * (step_percent * streak).min(max_percent) / 100,
+            Self::Points { step, max } => score + (step * streak).min(max),
+        }
+    }
+
+    /// Check that the bonus is within the supported ranges
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        match *self {
+            Self::None => Ok(()),
+            Self::Multiplier {
+                step_percent,
+                max_percent,
+            } => {
+                check_range("streak bonus step", step_percent, (0, 100), "%")?;
+                check_range("maximum streak bonus", max_percent, (0, 300), "%")
+            }
+            Self::Points { step, max } => {
+                check_range("streak bonus step", step, (0, SCORE_LIMIT), "")?;
+                check_range("maximum streak bonus", max, (0, SCORE_LIMIT), "")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn question_score_escalates_up_to_max() {
+        let rules = ScoringPolicy::Escalating.rules();
+        assert_eq!(rules.question_score(0), 500);
+        assert_eq!(rules.question_score(3), 800);
+        assert_eq!(rules.question_score(100), SCORE_LIMIT);
+        let flat = ScoringPolicy::Flat.rules();
+        assert_eq!(flat.question_score(0), flat.question_score(20));
+    }
+
+    #[test]
+    fn timed_score_decays_to_slowest_percent() {
+        let rules = ScoringRules::default();
+        assert_eq!(rules.timed_score(1000, 0, 10_000), 1000);
+        assert_eq!(rules.timed_score(1000, 5000, 10_000), 750);
+        assert_eq!(rules.timed_score(1000, 10_000, 10_000), 500);
+        // late submissions keep the slowest score
+        assert_eq!(rules.timed_score(1000, 20_000, 10_000), 500);
+        let accuracy = ScoringPolicy::AccuracyOnly.rules();
+        assert_eq!(accuracy.timed_score(1000, 9000, 10_000), 1000);
+        assert_eq!(accuracy.bonus(1000), 0);
+        assert_eq!(rules.bonus(1000), 200);
+    }
+
+    #[test]
+    fn validate_custom_rules() {
+        assert!(ScoringPolicy::SpeedHeavy.validate().is_ok());
+        let custom = |rules: ScoringRules| ScoringPolicy::Custom(rules).validate();
+        assert!(custom(ScoringRules::default()).is_ok());
+        assert!(custom(ScoringRules {
+            base_score: 0,
+            ..ScoringRules::default()
+        })
+        .is_err());
+        assert!(custom(ScoringRules {
+            max_score: 100,
+            ..ScoringRules::default()
+        })
+        .is_err());
+        assert!(custom(ScoringRules {
+            slowest_percent: 101,
+            ..ScoringRules::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn streak_bonus_is_capped() {
+        assert_eq!(StreakBonus::None.apply(1000, 5), 1000);
+        let multiplier = StreakBonus::Multiplier {
+            step_percent: 10,
+            max_percent: 100,
+        };
+        assert_eq!(multiplier.apply(1000, 0), 1000);
+        assert_eq!(multiplier.apply(1000, 3), 1300);
+        assert_eq!(multiplier.apply(1000, 20), 2000);
+        let points = StreakBonus::Points { step: 50, max: 500 };
+        assert_eq!(points.apply(1000, 2), 1100);
+        assert_eq!(points.apply(1000, 20), 1500);
+    }
+
+    #[test]
+    fn validate_streak_bonus() {
+        assert!(StreakBonus::None.validate().is_ok());
+        assert!(StreakBonus::Multiplier {
+            step_percent: 10,
+            max_percent: 300,
+        }
+        .validate()
+        .is_ok());
+        assert!(StreakBonus::Multiplier {
+            step_percent: 10,
+            max_percent: 301,
+        }
+        .validate()
+        .is_err());
+        assert!(StreakBonus::Points {
+            step: SCORE_LIMIT + 1,
+            max: 500,
+        }
+        .validate()
+        .is_err());
+    }
+}
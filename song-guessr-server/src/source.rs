@@ -0,0 +1,427 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Kind of items to search for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    #[default]
+    Playlist,
+    Album,
+    Artist,
+    Track,
+}
+
+/// An item returned by a music source's search
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SearchItem {
+    Playlist {
+        id: String,
+        name: String,
+        owner: String,
+    },
+    Album {
+        id: String,
+        name: String,
+        artists: Vec<String>,
+        year: Option<i32>,
+    },
+    Artist {
+        id: String,
+        name: String,
+    },
+    Track {
+        id: String,
+        name: String,
+        album: String,
+        artists: Vec<String>,
+    },
+}
+
+/// A collection of tracks that a game can be generated from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Collection {
+    Playlist {
+        id: String,
+    },
+    Album {
+        id: String,
+    },
+    /// All tracks of the artist's albums and singles
+    Artist {
+        id: String,
+    },
+    /// Tracks saved by the user that the source is logged in as
+    LikedSongs,
+    /// The user's most listened tracks over the given time range
+    TopTracks {
+        #[serde(default)]
+        time_range: TimeRange,
+    },
+    /// Tracks the user listened to recently
+    RecentlyPlayed,
+    /// A playlist imported from a file, which is stored by the server instead of the music source
+    Imported {
+        id: String,
+    },
+}
+
+/// Time range over which the user's top tracks are computed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeRange {
+    /// About the last 4 weeks
+    Short,
+    /// About the last 6 months
+    #[default]
+    Medium,
+    /// About the last year
+    Long,
+}
+
+impl fmt::Display for Collection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Collection::Playlist { id } => write!(f, "playlist {id}"),
+            Collection::Album { id } => write!(f, "album {id}"),
+            Collection::Artist { id } => write!(f, "artist {id}"),
+            Collection::LikedSongs => write!(f, "liked songs"),
+            Collection::TopTracks { time_range } => {
+                let time_range = match time_range {
+                    TimeRange::Short => "short",
+                    TimeRange::Medium => "medium",
+                    TimeRange::Long => "long",
+                };
+                write!(f, "top tracks ({time_range} term)")
+            }
+            Collection::RecentlyPlayed => write!(f, "recently played tracks"),
+            Collection::Imported { id } => write!(f, "imported playlist {id}"),
+        }
+    }
+}
+
+/// A track that can be used to generate game questions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Track {
+    pub id: String,
+    pub name: String,
+    pub album: String,
+    pub artists: Vec<String>,
+    // release year of the track's album
+    pub year: Option<i32>,
+    // track's popularity, ranging from 0 to 100
+    pub popularity: u32,
+    // International Standard Recording Code, used to match the track across music sources
+    #[serde(default)]
+    pub isrc: Option<String>,
+    pub snippet: Option<Snippet>,
+}
+
+/// Where the audio snippet of a track can be loaded from
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Snippet {
+    Url(String),
+    File(PathBuf),
+}
+
+/// Audio data of a snippet
+#[derive(Debug, Clone)]
+pub struct SnippetAudio {
+    pub data: Vec<u8>,
+    // MIME type of the data, e.g. "audio/mpeg", if known
+    pub content_type: Option<String>,
+}
+
+/// An entry of a playlist file, used to find the matching track in a music source
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrackQuery {
+    // file path or URI of the track
+    #[serde(default, alias = "path", alias = "uri")]
+    pub location: Option<String>,
+    #[serde(default, alias = "name")]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub artist: Option<String>,
+}
+
+/// ID of an album for sources that don't have album IDs, from its name and album artist
+/// so that different albums with the same name, e.g. "Greatest Hits", are told apart
+pub fn album_id(album: &str, album_artist: &str) -> String {
+    format!("{album_artist} - {album}")
+}
+
+/// Errors of a music source that are reported to the users
+#[derive(Debug, thiserror::Error)]
+pub enum SourceError {
+    #[error("music provider unauthorized, the server needs to re-authenticate with it")]
+    Unauthorized,
+    #[error("rate limited by the music provider, retry in {} s", retry_after.as_secs())]
+    RateLimited { retry_after: Duration },
+}
+
+/// A backend providing tracks and audio snippets for the game
+#[async_trait::async_trait]
+pub trait MusicSource: Send + Sync {
+    async fn search(&self, query: &str, kind: SearchKind) -> anyhow::Result<Vec<SearchItem>>;
+
+    /// Get all tracks of a collection, which may be cached by the source
+    /// unless `force_refresh` is set
+    async fn tracks(
+        &self,
+        collection: &Collection,
+        force_refresh: bool,
+    ) -> anyhow::Result<Vec<Track>>;
+
+    /// Find the track matching an entry of a playlist file
+    async fn find_track(&self, query: &TrackQuery) -> anyhow::Result<Option<Track>>;
+
+    /// Load the audio data of a track's snippet
+    async fn snippet_audio(&self, snippet: &Snippet) -> anyhow::Result<SnippetAudio>;
+}
+
+/// A music source serving a fixed list of tracks from memory, so that rooms can be
+/// tested without a music provider. Every playlist, as well as the user's own
+/// collections, contains all of its tracks.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    tracks: Vec<Track>,
+}
+
+#[cfg(test)]
+impl MemorySource {
+    pub fn new(tracks: Vec<Track>) -> Self {
+        Self { tracks }
+    }
+
+    // the first artist of tracks is their album artist
+    fn album_id(track: &Track) -> String {
+        let artist = track.artists.first().map(String::as_str);
+        album_id(&track.album, artist.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl MusicSource for MemorySource {
+    async fn search(&self, query: &str, kind: SearchKind) -> anyhow::Result<Vec<SearchItem>> {
+        let query = query.to_lowercase();
+        let matches = |s: &str| s.to_lowercase().contains(&query);
+        let tracks = self.tracks.iter().filter(|t| {
+            matches(&t.name) || matches(&t.album) || t.artists.iter().any(|a| matches(a))
+        });
+
+        let mut seen = std::collections::HashSet::new();
+        let items = match kind {
+            // playlists only exist by their ids
+            SearchKind::Playlist => Vec::new(),
+            SearchKind::Album => tracks
+                .map(|t| (Self::album_id(t), t))
+                .filter(|(id, _)| seen.insert(id.clone()))
+                .map(|(id, t)| SearchItem::Album {
+                    id,
+                    name: t.album.clone(),
+                    artists: t.artists.clone(),
+                    year: t.year,
+                })
+                .collect(),
+            SearchKind::Artist => tracks
+                .flat_map(|t| &t.artists)
+                .filter(|a| matches(a) && seen.insert(a.to_string()))
+                .map(|a| SearchItem::Artist {
+                    id: a.clone(),
+                    name: a.clone(),
+                })
+                .collect(),
+            SearchKind::Track => tracks
+                .map(|t| SearchItem::Track {
+                    id: t.id.clone(),
+                    name: t.name.clone(),
+                    album: t.album.clone(),
+                    artists: t.artists.clone(),
+                })
+                .collect(),
+        };
+        Ok(items)
+    }
+
+    async fn tracks(
+        &self,
+        collection: &Collection,
+        _force_refresh: bool,
+    ) -> anyhow::Result<Vec<Track>> {
+        let tracks = match collection {
+            Collection::Playlist { .. }
+            | Collection::LikedSongs
+            | Collection::TopTracks { .. }
+            | Collection::RecentlyPlayed => self.tracks.clone(),
+            Collection::Album { id } => self
+                .tracks
+                .iter()
+                .filter(|t| &Self::album_id(t) == id)
+                .cloned()
+                .collect(),
+            Collection::Artist { id } => self
+                .tracks
+                .iter()
+                .filter(|t| t.artists.contains(id))
+                .cloned()
+                .collect(),
+            Collection::Imported { .. } => {
+                anyhow::bail!("{collection} is not stored in the music source")
+            }
+        };
+        if tracks.is_empty() {
+            anyhow::bail!("{collection} not found");
+        }
+        Ok(tracks)
+    }
+
+    async fn find_track(&self, query: &TrackQuery) -> anyhow::Result<Option<Track>> {
+        let eq = |a: &str, b: &str| a.eq_ignore_ascii_case(b.trim());
+        Ok(self
+            .tracks
+            .iter()
+            .find(|t| {
+                if let Some(location) = &query.location {
+                    if eq(&t.id, location) {
+                        return true;
+                    }
+                }
+                query
+                    .title
+                    .as_deref()
+                    .is_some_and(|title| eq(&t.name, title))
+                    && query
+                        .artist
+                        .as_deref()
+                        .is_none_or(|artist| t.artists.iter().any(|a| eq(a, artist)))
+            })
+            .cloned())
+    }
+
+    async fn snippet_audio(&self, snippet: &Snippet) -> anyhow::Result<SnippetAudio> {
+        if !self
+            .tracks
+            .iter()
+            .any(|t| t.snippet.as_ref() == Some(snippet))
+        {
+            anyhow::bail!("snippet {snippet:?} not found");
+        }
+        // the snippet itself stands in for its audio
+        Ok(SnippetAudio {
+            data: format!("{snippet:?}").into_bytes(),
+            content_type: Some("audio/mpeg".to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(id: &str, name: &str, album: &str, artist: &str) -> Track {
+        Track {
+            id: id.to_string(),
+            name: name.to_string(),
+            album: album.to_string(),
+            artists: vec![artist.to_string()],
+            year: Some(2000),
+            popularity: 50,
+            isrc: None,
+            snippet: Some(Snippet::Url(format!("https://example.com/{id}.mp3"))),
+        }
+    }
+
+    fn source() -> MemorySource {
+        MemorySource::new(vec![
+            track("1", "Yellow", "Parachutes", "Coldplay"),
+            track("2", "Trouble", "Parachutes", "Coldplay"),
+            track("3", "Creep", "Pablo Honey", "Radiohead"),
+            track("4", "Karma Police", "OK Computer", "Radiohead"),
+            track("5", "Yellow", "Parachutes", "Other Band"),
+        ])
+    }
+
+    #[tokio::test]
+    async fn memory_source_tracks() {
+        let source = source();
+        let playlist = Collection::Playlist { id: "any".into() };
+        assert_eq!(source.tracks(&playlist, false).await.unwrap().len(), 5);
+
+        // albums with the same name by other artists aren't included
+        let album = Collection::Album {
+            id: album_id("Parachutes", "Coldplay"),
+        };
+        let tracks = source.tracks(&album, false).await.unwrap();
+        let names = tracks.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["Yellow", "Trouble"]);
+
+        let artist = Collection::Artist { id: "Muse".into() };
+        assert!(source.tracks(&artist, false).await.is_err());
+        assert!(MemorySource::default()
+            .tracks(&playlist, false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn memory_source_search() {
+        let source = source();
+        let albums = source.search("cold", SearchKind::Album).await.unwrap();
+        assert!(matches!(&albums[..], [SearchItem::Album { name, .. }] if name == "Parachutes"));
+        let albums = source
+            .search("parachutes", SearchKind::Album)
+            .await
+            .unwrap();
+        let ids: Vec<_> = albums
+            .iter()
+            .filter_map(|a| match a {
+                SearchItem::Album { id, .. } => Some(id.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ids, ["Coldplay - Parachutes", "Other Band - Parachutes"]);
+        let tracks = source.search("CREEP", SearchKind::Track).await.unwrap();
+        assert!(matches!(&tracks[..], [SearchItem::Track { id, .. }] if id == "3"));
+    }
+
+    #[tokio::test]
+    async fn memory_source_find_track() {
+        let source = source();
+        let find = |location: Option<&str>, title: Option<&str>, artist: Option<&str>| {
+            let query = TrackQuery {
+                location: location.map(String::from),
+                title: title.map(String::from),
+                artist: artist.map(String::from),
+            };
+            let source = source.clone();
+            async move { source.find_track(&query).await.unwrap().map(|t| t.id) }
+        };
+        assert_eq!(find(Some("2"), None, None).await.as_deref(), Some("2"));
+        assert_eq!(find(None, Some("creep"), None).await.as_deref(), Some("3"));
+        assert_eq!(
+            find(None, Some("Creep"), Some("Radiohead"))
+                .await
+                .as_deref(),
+            Some("3")
+        );
+        assert_eq!(find(None, Some("Creep"), Some("Coldplay")).await, None);
+    }
+
+    #[tokio::test]
+    async fn memory_source_snippet_audio() {
+        let source = source();
+        let snippet = Snippet::Url("https://example.com/1.mp3".into());
+        let audio = source.snippet_audio(&snippet).await.unwrap();
+        assert_eq!(audio.content_type.as_deref(), Some("audio/mpeg"));
+        assert!(!audio.data.is_empty());
+
+        let missing = Snippet::Url("https://example.com/9.mp3".into());
+        assert!(source.snippet_audio(&missing).await.is_err());
+    }
+}
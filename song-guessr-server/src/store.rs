@@ -0,0 +1,101 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::PathBuf;
+
+/// A directory of JSON records named by their IDs, such as quiz packs or imported playlists
+pub struct RecordDir {
+    dir: PathBuf,
+    // what the records are, used in error messages
+    kind: &'static str,
+}
+
+/// A record that isn't stored, or whose ID can't be one of a stored record
+#[derive(Debug, thiserror::Error)]
+#[error("{kind} {id} not found")]
+pub struct NotFound {
+    pub kind: &'static str,
+    pub id: String,
+}
+
+impl RecordDir {
+    pub fn new(dir: PathBuf, kind: &'static str) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, kind })
+    }
+
+    pub async fn read<T: DeserializeOwned>(&self, id: &str) -> anyhow::Result<T> {
+        let data = match tokio::fs::read(self.path(id)?).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(self.not_found(id).into())
+            }
+            Err(err) => return Err(err.into()),
+        };
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    pub async fn write<T: Serialize>(&self, id: &str, record: &T) -> anyhow::Result<()> {
+        tokio::fs::write(self.path(id)?, serde_json::to_vec(record)?).await?;
+        Ok(())
+    }
+
+    pub async fn remove(&self, id: &str) -> anyhow::Result<()> {
+        match tokio::fs::remove_file(self.path(id)?).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                Err(self.not_found(id).into())
+            }
+            result => Ok(result?),
+        }
+    }
+
+    /// Get all records of the directory, skipping files that aren't records
+    pub async fn list<T: DeserializeOwned>(&self) -> anyhow::Result<Vec<T>> {
+        let mut records = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let data = tokio::fs::read(entry.path()).await?;
+            if let Ok(record) = serde_json::from_slice(&data) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    fn path(&self, id: &str) -> Result<PathBuf, NotFound> {
+        // IDs are generated by `gen_id`, reject anything else to stay inside the directory
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(self.not_found(id));
+        }
+        Ok(self.dir.join(format!("{id}.json")))
+    }
+
+    fn not_found(&self, id: &str) -> NotFound {
+        NotFound {
+            kind: self.kind,
+            id: id.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn read_write_and_list_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let records = RecordDir::new(dir.path().to_path_buf(), "record").unwrap();
+        records.write("a1", &vec![1, 2]).await.unwrap();
+        assert_eq!(records.read::<Vec<u32>>("a1").await.unwrap(), [1, 2]);
+
+        // files that aren't records are skipped
+        std::fs::write(dir.path().join("other.txt"), "not JSON").unwrap();
+        assert_eq!(records.list::<Vec<u32>>().await.unwrap(), [vec![1, 2]]);
+
+        records.remove("a1").await.unwrap();
+        for id in ["a1", "../a1", ""] {
+            let err = records.read::<Vec<u32>>(id).await.unwrap_err();
+            assert!(err.is::<NotFound>(), "{err}");
+        }
+        assert!(records.remove("a1").await.unwrap_err().is::<NotFound>());
+    }
+}
@@ -0,0 +1,209 @@
+//! Normalization of song and album titles, which often contain version details
+//! such as "- 2011 Remaster", "(feat. Artist)" or "[Live at Wembley]"
+
+// parts of a title which are version details on their own, e.g. "(Live)" or "[Mono]"
+const VERSION_PARTS: [&str; 9] = [
+    "live",
+    "mono",
+    "stereo",
+    "demo",
+    "acoustic",
+    "instrumental",
+    "explicit",
+    "unplugged",
+    "single",
+];
+// phrases marking a part of a title as version details wherever they appear in it,
+// e.g. "2011 Remaster" or "Live at Wembley"
+const VERSION_PHRASES: [&str; 16] = [
+    "remaster",
+    "remastered",
+    "remix",
+    "live at",
+    "live from",
+    "live in",
+    "live on",
+    "bonus track",
+    "recorded at",
+    "recorded live",
+    "bbc session",
+    "peel session",
+    "motion picture",
+    "original soundtrack",
+    "from the movie",
+    "from the film",
+];
+// last words of a part of a title naming the kind of version, e.g. "Radio Edit",
+// "Extended Mix" or "Deluxe Edition"
+const VERSION_KINDS: [&str; 4] = ["version", "edit", "mix", "edition"];
+// first words of a part of a title naming featured artists, e.g. "(feat. Artist)"
+const FEATURE_PREFIXES: [&str; 3] = ["feat", "ft", "featuring"];
+
+/// Remove version details from a title for display, keeping bracketed parts of the
+/// title itself, e.g. "Song - 2011 Remaster" and "Song (feat. Artist)" become "Song",
+/// but "(I Can't Get No) Satisfaction" is kept as is
+pub fn clean(title: &str) -> String {
+    let mut cleaned = title.trim().to_string();
+    // most titles have no version details to look for
+    if !cleaned.contains(['(', '[', '-', '–']) {
+        return cleaned;
+    }
+    let len = cleaned.len();
+
+    // bracketed version details, e.g. "(Live)" or "[Deluxe Edition]"
+    let mut start = 0;
+    while let Some(open) = cleaned[start..].find(['(', '[']).map(|i| start + i) {
+        let close_char = if cleaned[open..].starts_with('(') {
+            ')'
+        } else {
+            ']'
+        };
+        let Some(close) = cleaned[open..].find(close_char).map(|i| open + i) else {
+            break;
+        };
+        if open > 0 && is_version(&cleaned[open + 1..close]) {
+            cleaned.replace_range(open..=close, "");
+            start = open;
+        } else {
+            start = close + 1;
+        }
+    }
+
+    // version details after a dash, e.g. "- 2011 Remaster" or "- Live at Wembley"
+    for dash in [" - ", " – "] {
+        if let Some(i) = cleaned.find(dash) {
+            if i > 0 && is_version(&cleaned[i + dash.len()..]) {
+                cleaned.truncate(i);
+            }
+        }
+    }
+
+    if cleaned.len() == len {
+        return cleaned;
+    }
+    // spaces around the removed parts are left, e.g. "Song (Live) Extra" -> "Song  Extra"
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    if cleaned.is_empty() {
+        title.trim().to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// Key of a title shared by the versions of the same work, used to compare titles
+pub fn key(title: &str) -> String {
+    fold(&clean(title))
+}
+
+/// Fold text into lowercase ASCII words, so that texts differing only in case,
+/// accents or punctuation are equal
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    let mut buf = [0; 4];
+    for c in text.chars() {
+        let ascii = match c {
+            // "&" and "and" are interchangeable, e.g. "Simon & Garfunkel"
+            '&' => " and ",
+            _ if c.is_ascii() => c.encode_utf8(&mut buf),
+            _ => deunicode::deunicode_char(c).unwrap_or(" "),
+        };
+        for b in ascii.bytes() {
+            if b.is_ascii_alphanumeric() {
+                folded.push(char::from(b.to_ascii_lowercase()));
+            } else if b != b'\'' && !folded.is_empty() && !folded.ends_with(' ') {
+                // apostrophes are dropped rather than splitting words, e.g. "Don't" -> "dont"
+                folded.push(' ');
+            }
+        }
+    }
+    if folded.ends_with(' ') {
+        folded.pop();
+    }
+    // a leading "the" is often left out, e.g. "Beatles" for "The Beatles"
+    if folded.starts_with("the ") {
+        folded.drain(..4);
+    }
+    folded
+}
+
+/// Whether a part of a title describes a version of the work rather than the work itself.
+/// Only whole phrases are matched, so that parts such as "(Live Forever)"
+/// or "- From Me to You" are kept.
+fn is_version(part: &str) -> bool {
+    let part = part.trim().to_lowercase();
+    // soundtracks name their work in quotes, e.g. `- From "Frozen"`
+    if part.starts_with("from \"") || part.starts_with("from \u{201c}") {
+        return true;
+    }
+    let words = part
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+    let (Some(first), Some(last)) = (words.first(), words.last()) else {
+        return false;
+    };
+    FEATURE_PREFIXES.contains(first)
+        || VERSION_PARTS.contains(&words.join(" ").as_str())
+        || (words.len() > 1 && VERSION_KINDS.contains(last))
+        || VERSION_PHRASES.iter().any(|phrase| {
+            let phrase = phrase.split(' ').collect::<Vec<_>>();
+            words.windows(phrase.len()).any(|window| window == phrase)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_removes_version_details() {
+        for (title, cleaned) in [
+            ("Song - 2011 Remaster", "Song"),
+            ("Song - Remastered 2009", "Song"),
+            ("Song (feat. Artist)", "Song"),
+            ("Song [Live at Wembley]", "Song"),
+            ("Song (Live)", "Song"),
+            ("Song - Radio Edit", "Song"),
+            ("Song (Taylor's Version)", "Song"),
+            ("Album (Deluxe Edition)", "Album"),
+            ("Song - From \"Frozen\"", "Song"),
+            ("Song (Live) (Mono)", "Song"),
+            ("Song (Live) Extra", "Song Extra"),
+            ("  Song  ", "Song"),
+        ] {
+            assert_eq!(clean(title), cleaned, "{title}");
+        }
+    }
+
+    #[test]
+    fn clean_keeps_parts_of_titles() {
+        for title in [
+            "(I Can't Get No) Satisfaction",
+            "Song (Live Forever)",
+            "Song - From Me to You",
+            "Song (With or Without You)",
+            "Song (Mix Tape)",
+            "Twenty-One",
+            "Unclosed (Live",
+            "(Live)",
+        ] {
+            assert_eq!(clean(title), title, "{title}");
+        }
+    }
+
+    #[test]
+    fn fold_ignores_case_accents_and_punctuation() {
+        assert_eq!(fold("Beyoncé"), "beyonce");
+        assert_eq!(fold("Don't Stop Me Now!"), "dont stop me now");
+        assert_eq!(fold("Simon & Garfunkel"), "simon and garfunkel");
+        assert_eq!(fold("The Beatles"), "beatles");
+        assert_eq!(fold("  --  "), "");
+    }
+
+    #[test]
+    fn key_is_shared_by_versions() {
+        assert_eq!(key("Song - 2011 Remaster"), key("song"));
+        assert_eq!(key("Song (feat. Artist)"), key("SONG"));
+        assert_ne!(key("Song (Live Forever)"), key("Song"));
+    }
+}
@@ -1,6 +1,6 @@
 import { useEffect, useMemo, useState } from "react";
-import { PlayingGameState, User } from "./model.tsx";
-import { Button, Flex, Progress, Text } from "@chakra-ui/react";
+import { AnswerMode, PlayingGameState, User } from "./model.tsx";
+import { Button, Flex, Input, Progress, Text } from "@chakra-ui/react";
 import { put } from "./utils.tsx";
 import Scoreboard from "./components/Scoreboard.tsx";
 import { Howl } from "howler";
@@ -13,20 +13,30 @@ const Game: React.FC<{
   isOwner: boolean;
 }> = ({ ws, state, user, room, isOwner }) => {
   const [selectedChoice, setSelectedChoice] = useState<number | null>(null);
+  // typed answer of free-text questions
+  const [guess, setGuess] = useState<string>("");
+  const [guessSubmitted, setGuessSubmitted] = useState<boolean>(false);
   const [audioCurrentTime, setAudioCurrentTime] = useState<number>(0);
   const [audioPlayable, setAudioPlayable] = useState<boolean>(true);
   // construct a timer to measure the elapsed time of the current song's progress
   const [timer] = useState(performance.now() - state.song_progress_ms);
+  // time left before the song starts playing
+  const [preRollLeft, setPreRollLeft] = useState<number>(
+    Math.max(0, -state.song_progress_ms),
+  );
+
+  const songUrl = `/api/room/${room}/audio/${state.question_id}`;
 
   const audio = useMemo(() => {
     const audio = new Howl({
-      src: [state.question.song_url],
+      src: [songUrl],
       format: ["mp3"],
       html5: true,
       onplayerror: () => {
         setAudioPlayable(false);
       },
-      autoplay: true,
+      // songs start after the question's pre-roll
+      autoplay: performance.now() >= timer,
       volume: 0.5,
     });
 
@@ -37,18 +47,25 @@ const Game: React.FC<{
     });
 
     return audio;
-  }, [state.question.song_url, timer]);
+  }, [songUrl, timer]);
 
   useEffect(() => {
     const interval = setInterval(() => {
       setAudioCurrentTime(audio.seek());
+      setPreRollLeft(Math.max(0, timer - performance.now()));
     }, 100);
+    const preRoll = setTimeout(() => {
+      if (!audio.playing()) {
+        audio.play();
+      }
+    }, timer - performance.now());
 
     return () => {
       audio.pause();
       clearInterval(interval);
+      clearTimeout(preRoll);
     };
-  }, [audio]);
+  }, [audio, timer]);
 
   const handleChoiceSubmit = (selectedChoice: number) => {
     setSelectedChoice(selectedChoice);
@@ -63,6 +80,19 @@ const Game: React.FC<{
     );
   };
 
+  const handleGuessSubmit = () => {
+    setGuessSubmitted(true);
+    ws.send(
+      JSON.stringify({
+        type: "UserSubmitted",
+        user_name: user.name,
+        user_id: user.id,
+        text: guess,
+        submitted_at_ms: Math.round(audio.seek() * 1000),
+      }),
+    );
+  };
+
   // this is a hack to get the audio to play on the first render
   // because the audio autoplay must be triggered by a user gesture
   // more details: see https://developer.chrome.com/blog/autoplay/
@@ -95,9 +125,18 @@ const Game: React.FC<{
         </Text>
       </Text>
 
+      {preRollLeft > 0 && (
+        <Text textStyle="lg" textAlign="center">
+          Get ready... {Math.ceil(preRollLeft / 1000)}
+        </Text>
+      )}
+
       {audio.playing() && (
         <Progress.Root
-          value={Math.min(100, (audioCurrentTime / 10) * 100)}
+          value={Math.min(
+            100,
+            ((audioCurrentTime * 1000) / state.settings.answer_time_ms) * 100,
+          )}
           colorPalette="green"
         >
           <Progress.Track>
@@ -110,6 +149,35 @@ const Game: React.FC<{
         <Text textStyle="lg" fontWeight="bold">
           Guess the {state.question.question_type}
         </Text>
+        {state.question.answer_mode === AnswerMode.FreeText && (
+          <form
+            onSubmit={(e) => {
+              e.preventDefault();
+              if (guess.trim() !== "") {
+                handleGuessSubmit();
+              }
+            }}
+          >
+            <Flex gap="2" margin="1">
+              <Input
+                value={guess}
+                onChange={(e) => setGuess(e.target.value)}
+                disabled={guessSubmitted || !audio.playing()}
+                placeholder={`Type the ${state.question.question_type}`}
+                width="15em"
+                autoFocus
+              />
+              <Button
+                type="submit"
+                disabled={
+                  guessSubmitted || !audio.playing() || guess.trim() === ""
+                }
+              >
+                Submit
+              </Button>
+            </Flex>
+          </form>
+        )}
         {state.question.choices.map((choice, index) => (
           <Button
             key={index}
@@ -1,4 +1,5 @@
-import { Button, Flex } from "@chakra-ui/react";
+import { Button, Flex, Text } from "@chakra-ui/react";
+import { useState } from "react";
 import { EndedGameState, User } from "./model";
 import { put } from "./utils";
 import Scoreboard from "./components/Scoreboard";
@@ -9,18 +10,36 @@ const GameResults: React.FC<{
   user: User;
   isOwner: boolean;
 }> = ({ room, state, user, isOwner }) => {
+  const [error, setError] = useState<string | null>(null);
+
   return (
     <Flex direction="column" gap="4">
       <Scoreboard title="Results" users={state.users} />
+      {state.seed !== null && <Text>Game seed: {state.seed}</Text>}
       {isOwner && (
         <Flex direction="column" gap="2">
           <Button
-            onClick={() => {
-              put(`/api/room/${room}/restart`, { user_id: user.id });
+            onClick={async () => {
+              try {
+                setError(null);
+                await put(`/api/room/${room}/restart`, { user_id: user.id });
+              } catch (err) {
+                console.error(err);
+                setError((err as Error).message);
+              }
             }}
           >
             Restart Game
           </Button>
+          {error !== null && <Text color="red.500">{error}</Text>}
+          <Button variant="outline" asChild>
+            <a
+              href={`/api/room/${room}/export?user_id=${encodeURIComponent(user.id)}`}
+              download
+            >
+              Download Questions
+            </a>
+          </Button>
           <Button
             onClick={() => {
               put(`/api/room/${room}/reset`, { user_id: user.id });
@@ -1,13 +1,17 @@
 import { Flex, Heading, List, Text } from "@chakra-ui/react";
-import { WaitingForNextQuestionState } from "./model";
+import { Verdict, WaitingForNextQuestionState } from "./model";
 import Scoreboard from "./components/Scoreboard";
 
+const verdictLabels: Record<Verdict, { label: string; color: string }> = {
+  correct: { label: "accepted", color: "green.500" },
+  close: { label: "close", color: "orange.400" },
+  wrong: { label: "wrong", color: "red.500" },
+};
+
 const QuestionResults: React.FC<{ state: WaitingForNextQuestionState }> = ({
   state,
 }) => {
-  state.correct_submissions.sort(
-    (a, b) => a.submitted_at_ms - b.submitted_at_ms,
-  );
+  state.submissions.sort((a, b) => a.submitted_at_ms - b.submitted_at_ms);
   return (
     <Flex direction="column" gap="2">
       <div>
@@ -18,14 +22,20 @@ const QuestionResults: React.FC<{ state: WaitingForNextQuestionState }> = ({
           </Text>
         </Text>
       </div>
-      {state.correct_submissions.length > 0 && (
+      {state.submissions.length > 0 && (
         <Flex direction="column" gap="2">
           <Heading size="xl">Results</Heading>
           <List.Root>
-            {state.correct_submissions.map((sub, i) => {
+            {state.submissions.map((sub, i) => {
+              const verdict = verdictLabels[sub.verdict ?? "wrong"];
               return (
                 <List.Item key={i}>
-                  {sub.user_name}: {sub.submitted_at_ms / 1000}s
+                  {sub.user_name}
+                  {sub.text !== null && `: "${sub.text}"`}&nbsp;
+                  <Text color={verdict.color} as="span">
+                    ({verdict.label})
+                  </Text>
+                  , {sub.submitted_at_ms / 1000}s
                 </List.Item>
               );
             })}
@@ -35,7 +45,7 @@ const QuestionResults: React.FC<{ state: WaitingForNextQuestionState }> = ({
       <Scoreboard
         title="Scoreboard"
         users={state.users}
-        submissions={state.correct_submissions}
+        submissions={state.submissions}
       />
     </Flex>
   );
@@ -0,0 +1,176 @@
+import { AnswerMode, GameSettings, QuizPack, User } from "./model.tsx";
+import { useEffect, useState } from "react";
+import { del, get, post, put } from "./utils.tsx";
+import {
+  Heading,
+  Input,
+  Field,
+  Button,
+  Checkbox,
+  Flex,
+  Text,
+} from "@chakra-ui/react";
+import LoadingPopup from "./components/LoadingPopup.tsx";
+
+// owner tokens of the packs uploaded from this browser, required to delete them
+const OWNER_TOKENS_KEY = "packOwnerTokens";
+
+function getOwnerTokens(): Record<string, string> {
+  return JSON.parse(localStorage.getItem(OWNER_TOKENS_KEY) ?? "{}");
+}
+
+function setOwnerTokens(tokens: Record<string, string>) {
+  localStorage.setItem(OWNER_TOKENS_KEY, JSON.stringify(tokens));
+}
+import GameSettingsFields, {
+  defaultGameSettings,
+} from "./components/GameSettingsFields.tsx";
+
+const QuizPacks: React.FC<{ user: User; room: string }> = ({ room, user }) => {
+  const [packs, setPacks] = useState<Array<QuizPack>>([]);
+  const [ownerTokens, setOwnerTokensState] =
+    useState<Record<string, string>>(getOwnerTokens);
+  const [loading, setLoading] = useState<boolean>(false);
+  const [error, setError] = useState<string | null>(null);
+  const [freeText, setFreeText] = useState<boolean>(false);
+  const [settings, setSettings] = useState<GameSettings>(defaultGameSettings);
+
+  const loadPacks = async () => {
+    try {
+      const response = await get("/api/packs");
+      setPacks(await response.json());
+    } catch (err) {
+      console.error(err);
+    }
+  };
+
+  useEffect(() => {
+    loadPacks();
+  }, []);
+
+  const uploadPack = async (file: File) => {
+    try {
+      setError(null);
+      setLoading(true);
+      const response = await post("/api/pack", {
+        content: await file.text(),
+        format: file.name.endsWith(".toml") ? "toml" : "json",
+      });
+      const { id, owner_token } = await response.json();
+      const tokens = { ...getOwnerTokens(), [id]: owner_token };
+      setOwnerTokens(tokens);
+      setOwnerTokensState(tokens);
+      await loadPacks();
+    } catch (err) {
+      console.error(err);
+      setError((err as Error).message);
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  const deletePack = async (pack: QuizPack) => {
+    try {
+      setError(null);
+      const token = encodeURIComponent(ownerTokens[pack.id]);
+      await del(`/api/pack/${pack.id}?owner_token=${token}`);
+      const tokens = getOwnerTokens();
+      delete tokens[pack.id];
+      setOwnerTokens(tokens);
+      setOwnerTokensState(tokens);
+      await loadPacks();
+    } catch (err) {
+      console.error(err);
+      setError((err as Error).message);
+    }
+  };
+
+  const startPack = async (pack: QuizPack) => {
+    try {
+      setError(null);
+      setLoading(true);
+      await put(`/api/room/${room}/new_pack_game`, {
+        user_id: user.id,
+        pack_id: pack.id,
+        answer_mode: freeText
+          ? AnswerMode.FreeText
+          : AnswerMode.MultipleChoice,
+        settings,
+      });
+    } catch (err) {
+      console.error(err);
+      setError((err as Error).message);
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  return (
+    <div>
+      <Heading size="xl">Quiz packs</Heading>
+
+      <Flex gap="3" direction="column">
+        <Field.Root>
+          <Field.Label>Upload a quiz pack (JSON, TOML)</Field.Label>
+          <Input
+            type="file"
+            accept=".json,.toml"
+            onChange={(e) => {
+              const file = e.target.files?.[0];
+              if (file !== undefined) {
+                uploadPack(file);
+              }
+              e.target.value = ""; // allow uploading the same file again
+            }}
+          />
+        </Field.Root>
+
+        <Checkbox.Root
+          checked={freeText}
+          onCheckedChange={(e) => {
+            setFreeText(e.checked === true);
+          }}
+        >
+          <Checkbox.HiddenInput />
+          <Checkbox.Control />
+          <Checkbox.Label>Type answers instead of picking them</Checkbox.Label>
+        </Checkbox.Root>
+
+        <GameSettingsFields settings={settings} onChange={setSettings} />
+
+        <Flex direction="column" gap="1">
+          {packs.map((pack) => (
+            <Flex key={pack.id} align="center" gap="2">
+              <Button size="xs" onClick={() => startPack(pack)}>
+                Play
+              </Button>
+              <Text flex="1">
+                {pack.name} ({pack.num_questions} questions)
+              </Text>
+              <Button size="xs" variant="outline" asChild>
+                <a href={`/api/pack/${pack.id}`} download>
+                  Download
+                </a>
+              </Button>
+              {ownerTokens[pack.id] !== undefined && (
+                <Button
+                  size="xs"
+                  variant="outline"
+                  onClick={() => deletePack(pack)}
+                >
+                  Delete
+                </Button>
+              )}
+            </Flex>
+          ))}
+        </Flex>
+
+        {error !== null && <Text color="red.500">{error}</Text>}
+      </Flex>
+
+      <LoadingPopup loading={loading} />
+    </div>
+  );
+};
+
+export default QuizPacks;
@@ -1,6 +1,17 @@
-import { Playlist, QuestionType, User } from "./model.tsx";
+import {
+  AnswerMode,
+  Collection,
+  Difficulty,
+  GameSettings,
+  GameSource,
+  QuestionType,
+  SearchItem,
+  SearchKind,
+  TimeRange,
+  User,
+} from "./model.tsx";
 import { useState } from "react";
-import { get, put } from "./utils.tsx";
+import { get, post, put } from "./utils.tsx";
 import {
   createListCollection,
   Heading,
@@ -8,16 +19,33 @@ import {
   Field,
   Select,
   Button,
+  Checkbox,
   Flex,
+  Text,
 } from "@chakra-ui/react";
-import { Radio, RadioGroup } from "./components/ui/radio.tsx";
 import LoadingPopup from "./components/LoadingPopup.tsx";
+import GameSettingsFields, {
+  defaultGameSettings,
+} from "./components/GameSettingsFields.tsx";
 
 type NewGameRequest = {
   user_id: string;
-  playlist_id: string;
+  sources: Array<GameSource>;
   num_questions: number;
   question_types: Array<QuestionType>;
+  num_choices?: number;
+  difficulty: Difficulty;
+  seed?: number;
+  partial_credit: boolean;
+  answer_mode: AnswerMode;
+  settings: GameSettings;
+};
+
+type ImportPlaylistResponse = {
+  id: string;
+  name: string;
+  num_tracks: number;
+  unresolved: Array<unknown>;
 };
 
 const numQuestionsChoices = createListCollection({
@@ -26,33 +54,120 @@ const numQuestionsChoices = createListCollection({
   }),
 });
 
+const numChoicesChoices = createListCollection({
+  items: [
+    // questions have fewer choices if the sources don't have enough songs
+    { value: "auto", label: "Up to 4" },
+    ...Array.from({ length: 7 }, (_, i) => {
+      return { value: String(i + 2), label: String(i + 2) };
+    }),
+  ],
+});
+
+const difficultyChoices = createListCollection({
+  items: [
+    { value: Difficulty.Easy, label: "Easy" },
+    { value: Difficulty.Normal, label: "Normal" },
+    { value: Difficulty.Hard, label: "Hard" },
+  ],
+});
+
 const questionTypeChoices = createListCollection({
   items: [
     { value: QuestionType.Song, label: "Song" },
     { value: QuestionType.Album, label: "Album" },
     { value: QuestionType.Artist, label: "Artist" },
+    { value: QuestionType.Year, label: "Release year" },
+    { value: QuestionType.Decade, label: "Release decade" },
   ],
 });
 
+const searchKindChoices = createListCollection({
+  items: [
+    { value: SearchKind.Playlist, label: "Playlist" },
+    { value: SearchKind.Album, label: "Album" },
+    { value: SearchKind.Artist, label: "Artist" },
+  ],
+});
+
+const describe = (item: SearchItem) => {
+  switch (item.kind) {
+    case SearchKind.Playlist:
+      return `${item.name} by ${item.owner}`;
+    case SearchKind.Album: {
+      const year = item.year !== null ? ` (${item.year})` : "";
+      return `${item.name} by ${item.artists.join(", ")}${year}`;
+    }
+    case SearchKind.Artist:
+      return `All songs by ${item.name}`;
+    case SearchKind.Track:
+      return `${item.name} by ${item.artists.join(", ")}`;
+  }
+};
+
+// sources from the library of the Spotify user that the server is logged in as
+const libraryCollections: Array<{ collection: Collection; label: string }> = [
+  { collection: { kind: "liked_songs" }, label: "Liked songs" },
+  {
+    collection: { kind: "top_tracks", time_range: TimeRange.Short },
+    label: "Top tracks (last 4 weeks)",
+  },
+  {
+    collection: { kind: "top_tracks", time_range: TimeRange.Medium },
+    label: "Top tracks (last 6 months)",
+  },
+  {
+    collection: { kind: "top_tracks", time_range: TimeRange.Long },
+    label: "Top tracks (last year)",
+  },
+  { collection: { kind: "recently_played" }, label: "Recently played" },
+];
+
+const collectionKey = (collection: Collection) => {
+  switch (collection.kind) {
+    case "liked_songs":
+    case "recently_played":
+      return collection.kind;
+    case "top_tracks":
+      return `${collection.kind}:${collection.time_range}`;
+    default:
+      return `${collection.kind}:${collection.id}`;
+  }
+};
+
 const Search: React.FC<{ user: User; room: string }> = ({ room, user }) => {
   const [query, setQuery] = useState<string>("");
-  const [results, setResults] = useState<Array<Playlist>>([]);
+  const [kind, setKind] = useState<SearchKind>(SearchKind.Playlist);
+  const [results, setResults] = useState<Array<SearchItem>>([]);
   const [numQuestions, setNumQuestions] = useState<number>(15);
+  const [partialCredit, setPartialCredit] = useState<boolean>(false);
+  const [freeText, setFreeText] = useState<boolean>(false);
+  const [numChoices, setNumChoices] = useState<string>("auto");
+  const [difficulty, setDifficulty] = useState<Difficulty>(Difficulty.Normal);
+  const [settings, setSettings] = useState<GameSettings>(defaultGameSettings);
+  // seed of the game, to replay a previous game
+  const [seed, setSeed] = useState<string>("");
   const [questionTypes, setQuestionTypes] = useState<Array<QuestionType>>([
     QuestionType.Song,
   ]);
-  const [playlistId, setPlaylistId] = useState<string>("");
+  // selected sources, along with their descriptions
+  const [sources, setSources] = useState<
+    Array<{ source: GameSource; description: string }>
+  >([]);
   const [loading, setLoading] = useState<boolean>(false);
+  const [error, setError] = useState<string | null>(null);
+  const [importMessage, setImportMessage] = useState<string | null>(null);
 
-  const searchPlaylists = async () => {
+  const searchCollections = async () => {
     if (query !== "") {
       try {
         setLoading(true);
-        const response = await get(`/api/search?query=${query}`);
+        const response = await get(
+          `/api/search?query=${encodeURIComponent(query)}&kind=${kind}`,
+        );
         const data = await response.json();
         setLoading(false);
 
-        setPlaylistId("");
         setResults(data);
         setNumQuestions(15);
       } catch (err) {
@@ -61,23 +176,90 @@ const Search: React.FC<{ user: User; room: string }> = ({ room, user }) => {
     }
   };
 
+  const addSource = (collection: Collection, description: string) => {
+    const key = collectionKey(collection);
+    if (sources.some(({ source }) => collectionKey(source) === key)) {
+      return;
+    }
+    setSources([
+      ...sources,
+      { source: { ...collection, weight: 1 }, description },
+    ]);
+  };
+
+  const addSearchItem = (item: SearchItem) => {
+    if (item.kind !== SearchKind.Track) {
+      addSource({ kind: item.kind, id: item.id }, describe(item));
+    }
+  };
+
+  const importPlaylist = async (file: File) => {
+    try {
+      setError(null);
+      setImportMessage(null);
+      setLoading(true);
+      const response = await post(`/api/room/${room}/import`, {
+        user_id: user.id,
+        content: await file.text(),
+        name: file.name.replace(/\.[^.]*$/, ""),
+      });
+      const data: ImportPlaylistResponse = await response.json();
+      addSource(
+        { kind: "imported", id: data.id },
+        `${data.name} (imported, ${data.num_tracks} songs)`,
+      );
+      if (data.unresolved.length > 0) {
+        setImportMessage(
+          `${data.unresolved.length} songs of ${data.name} could not be found`,
+        );
+      }
+    } catch (err) {
+      console.error(err);
+      setError((err as Error).message);
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  const setSourceWeight = (index: number, weight: number) => {
+    setSources(
+      sources.map((s, i) =>
+        i === index ? { ...s, source: { ...s.source, weight } } : s,
+      ),
+    );
+  };
+
+  const removeSource = (index: number) => {
+    setSources(sources.filter((_, i) => i !== index));
+  };
+
   const newGame = async () => {
     const body: NewGameRequest = {
       user_id: user.id,
-      playlist_id: playlistId,
+      sources: sources.map(({ source }) => source),
       num_questions: numQuestions,
       question_types: questionTypes,
+      // choices of free-text questions are hidden
+      num_choices:
+        !freeText && numChoices !== "auto" ? Number(numChoices) : undefined,
+      difficulty,
+      seed: seed !== "" ? Number(seed) : undefined,
+      partial_credit: partialCredit,
+      answer_mode: freeText ? AnswerMode.FreeText : AnswerMode.MultipleChoice,
+      settings,
     };
     try {
+      setError(null);
       await put(`/api/room/${room}/new_game`, body);
     } catch (err) {
       console.error(err);
+      setError((err as Error).message);
     }
   };
 
   return (
     <div>
-      <Heading size="xl">Search for playlist</Heading>
+      <Heading size="xl">Search for music</Heading>
 
       <form
         onSubmit={async (e) => {
@@ -88,6 +270,26 @@ const Search: React.FC<{ user: User; room: string }> = ({ room, user }) => {
         }}
       >
         <Flex gap="3" direction="column">
+          <Select.Root
+            collection={searchKindChoices}
+            value={[kind]}
+            onValueChange={(e) => {
+              setKind(e.items[0].value);
+            }}
+          >
+            <Select.Label>Search for</Select.Label>
+            <Select.Trigger>
+              <Select.ValueText />
+            </Select.Trigger>
+            <Select.Content>
+              {searchKindChoices.items.map((item) => (
+                <Select.Item color="black" item={item} key={item.value}>
+                  {item.label}
+                </Select.Item>
+              ))}
+            </Select.Content>
+          </Select.Root>
+
           <Field.Root>
             <Field.Label>Search</Field.Label>
             <Input
@@ -98,28 +300,78 @@ const Search: React.FC<{ user: User; room: string }> = ({ room, user }) => {
               onKeyDown={(e) => {
                 if (e.key === "Enter") {
                   e.preventDefault(); // prevent form submission on Enter
-                  searchPlaylists();
+                  searchCollections();
                 }
               }}
             />
           </Field.Root>
 
-          <RadioGroup
-            value={playlistId}
-            onValueChange={(e) => {
-              setPlaylistId(e.value);
-            }}
-          >
+          <Flex direction="column" gap="1">
+            {results.slice(0, 10).map((result) => (
+              <Flex key={result.id} align="center" gap="2">
+                <Button size="xs" onClick={() => addSearchItem(result)}>
+                  Add
+                </Button>
+                <Text>{describe(result)}</Text>
+              </Flex>
+            ))}
+          </Flex>
+
+          <Flex gap="2" wrap="wrap">
+            {libraryCollections.map(({ collection, label }) => (
+              <Button
+                key={collectionKey(collection)}
+                size="xs"
+                variant="outline"
+                onClick={() => addSource(collection, label)}
+              >
+                {label}
+              </Button>
+            ))}
+          </Flex>
+
+          <Field.Root>
+            <Field.Label>Import a playlist file (M3U, XSPF, JSON)</Field.Label>
+            <Input
+              type="file"
+              accept=".m3u,.m3u8,.xspf,.json"
+              onChange={(e) => {
+                const file = e.target.files?.[0];
+                if (file !== undefined) {
+                  importPlaylist(file);
+                }
+                e.target.value = ""; // allow importing the same file again
+              }}
+            />
+          </Field.Root>
+          {importMessage !== null && <Text>{importMessage}</Text>}
+
+          {sources.length > 0 && (
             <Flex direction="column" gap="1">
-              {results.slice(0, 10).map((result) => (
-                <Radio key={result.id} value={result.id}>
-                  {result.name} by {result.owner.display_name}
-                </Radio>
+              <Heading size="md">Game sources</Heading>
+              {sources.map(({ source, description }, index) => (
+                <Flex key={collectionKey(source)} align="center" gap="2">
+                  <Button size="xs" onClick={() => removeSource(index)}>
+                    Remove
+                  </Button>
+                  <Text flex="1">{description}</Text>
+                  <Text>Weight</Text>
+                  <Input
+                    type="number"
+                    width="20"
+                    min={0.1}
+                    step={0.1}
+                    value={source.weight}
+                    onChange={(e) => {
+                      setSourceWeight(index, Number(e.target.value));
+                    }}
+                  />
+                </Flex>
               ))}
             </Flex>
-          </RadioGroup>
+          )}
 
-          {results.length > 0 && (
+          {sources.length > 0 && (
             <Select.Root
               collection={numQuestionsChoices}
               // @ts-expect-error: value of Select component is array of numbers
@@ -142,7 +394,7 @@ const Search: React.FC<{ user: User; room: string }> = ({ room, user }) => {
             </Select.Root>
           )}
 
-          {results.length > 0 && (
+          {sources.length > 0 && (
             <Select.Root
               multiple
               collection={questionTypeChoices}
@@ -165,14 +417,110 @@ const Search: React.FC<{ user: User; room: string }> = ({ room, user }) => {
             </Select.Root>
           )}
 
-          {results.length > 0 && (
+          {sources.length > 0 && !freeText && (
+            <Select.Root
+              collection={numChoicesChoices}
+              value={[numChoices]}
+              onValueChange={(e) => {
+                setNumChoices(e.items[0].value);
+              }}
+            >
+              <Select.Label>Number of choices</Select.Label>
+              <Select.Trigger>
+                <Select.ValueText />
+              </Select.Trigger>
+              <Select.Content>
+                {numChoicesChoices.items.map((item) => (
+                  <Select.Item color="black" item={item} key={item.value}>
+                    {item.label}
+                  </Select.Item>
+                ))}
+              </Select.Content>
+            </Select.Root>
+          )}
+
+          {sources.length > 0 && (
+            <Select.Root
+              collection={difficultyChoices}
+              value={[difficulty]}
+              onValueChange={(e) => {
+                setDifficulty(e.items[0].value);
+              }}
+            >
+              <Select.Label>Difficulty</Select.Label>
+              <Select.Trigger>
+                <Select.ValueText />
+              </Select.Trigger>
+              <Select.Content>
+                {difficultyChoices.items.map((item) => (
+                  <Select.Item color="black" item={item} key={item.value}>
+                    {item.label}
+                  </Select.Item>
+                ))}
+              </Select.Content>
+            </Select.Root>
+          )}
+
+          {sources.length > 0 &&
+            questionTypes.some(
+              (t) => t === QuestionType.Year || t === QuestionType.Decade,
+            ) && (
+              <Checkbox.Root
+                checked={partialCredit}
+                onCheckedChange={(e) => {
+                  setPartialCredit(e.checked === true);
+                }}
+              >
+                <Checkbox.HiddenInput />
+                <Checkbox.Control />
+                <Checkbox.Label>Partial credit for close years</Checkbox.Label>
+              </Checkbox.Root>
+            )}
+
+          {sources.length > 0 && (
+            <Checkbox.Root
+              checked={freeText}
+              onCheckedChange={(e) => {
+                setFreeText(e.checked === true);
+              }}
+            >
+              <Checkbox.HiddenInput />
+              <Checkbox.Control />
+              <Checkbox.Label>
+                Type answers instead of picking them
+              </Checkbox.Label>
+            </Checkbox.Root>
+          )}
+
+          {sources.length > 0 && (
+            <GameSettingsFields settings={settings} onChange={setSettings} />
+          )}
+
+          {sources.length > 0 && (
+            <Field.Root>
+              <Field.Label>Seed (optional)</Field.Label>
+              <Input
+                type="number"
+                min={0}
+                step={1}
+                value={seed}
+                onChange={(e) => {
+                  setSeed(e.target.value);
+                }}
+              />
+            </Field.Root>
+          )}
+
+          {sources.length > 0 && (
             <Button
               type="submit"
-              disabled={playlistId === "" || questionTypes.length == 0}
+              disabled={sources.length === 0 || questionTypes.length == 0}
             >
               New Game
             </Button>
           )}
+
+          {error !== null && <Text color="red.500">{error}</Text>}
         </Flex>
       </form>
 
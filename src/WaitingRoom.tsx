@@ -1,6 +1,7 @@
 import { User, WaitingGameState } from "./model";
 import React from "react";
 import Search from "./Search";
+import QuizPacks from "./QuizPacks";
 import { Heading, List, Flex, Text } from "@chakra-ui/react";
 
 const WaitingRoom: React.FC<{
@@ -20,6 +21,7 @@ const WaitingRoom: React.FC<{
         </List.Root>
       </div>
       {isOwner && <Search room={room} user={user} />}
+      {isOwner && <QuizPacks room={room} user={user} />}
       {!isOwner && (
         <Text textStyle="md">Waiting for the owner to start the game...</Text>
       )}
@@ -0,0 +1,238 @@
+import { createListCollection, Field, Input, Select } from "@chakra-ui/react";
+import {
+  GameSettings,
+  ScoringPreset,
+  ScoringRules,
+  StreakBonus,
+} from "../model";
+
+// default rules of custom scoring, the same as the escalating preset
+const defaultScoringRules: ScoringRules = {
+  base_score: 500,
+  score_step: 100,
+  max_score: 2000,
+  slowest_percent: 50,
+  fastest_bonus_percent: 20,
+};
+
+const defaultGameSettings: GameSettings = {
+  answer_time_ms: 10000,
+  reveal_time_ms: 1500,
+  pre_roll_ms: 0,
+  scoring: { preset: ScoringPreset.Escalating },
+  wrong_answer_penalty_percent: 0,
+  streak_bonus: { kind: "none" },
+};
+
+const secondsChoices = (seconds: Array<number>, zeroLabel?: string) =>
+  createListCollection({
+    items: seconds.map((s) => {
+      return {
+        value: String(s * 1000),
+        label: s === 0 && zeroLabel !== undefined ? zeroLabel : `${s}s`,
+      };
+    }),
+  });
+
+// songs' previews are at most 30 seconds long
+const answerTimeChoices = secondsChoices([5, 10, 15, 20, 30]);
+const revealTimeChoices = secondsChoices([1.5, 3, 5, 10]);
+const preRollChoices = secondsChoices([0, 3, 5], "None");
+
+const timingFields: Array<{
+  key: "answer_time_ms" | "reveal_time_ms" | "pre_roll_ms";
+  label: string;
+  choices: typeof answerTimeChoices;
+}> = [
+  { key: "answer_time_ms", label: "Answer time", choices: answerTimeChoices },
+  {
+    key: "reveal_time_ms",
+    label: "Answer shown for",
+    choices: revealTimeChoices,
+  },
+  {
+    key: "pre_roll_ms",
+    label: "Countdown before songs",
+    choices: preRollChoices,
+  },
+];
+
+const scoringPresetChoices = createListCollection({
+  items: [
+    { value: ScoringPreset.Escalating, label: "Escalating" },
+    { value: ScoringPreset.Flat, label: "Flat" },
+    { value: ScoringPreset.SpeedHeavy, label: "Speed matters most" },
+    { value: ScoringPreset.AccuracyOnly, label: "Accuracy only" },
+    { value: ScoringPreset.Custom, label: "Custom" },
+  ],
+});
+
+const penaltyChoices = createListCollection({
+  items: [0, 25, 50, 100].map((p) => {
+    return { value: String(p), label: p === 0 ? "None" : `${p}%` };
+  }),
+});
+
+const streakBonuses: Array<{ label: string; bonus: StreakBonus }> = [
+  { label: "None", bonus: { kind: "none" } },
+  {
+    label: "+10% per correct answer in a row, up to double",
+    bonus: { kind: "multiplier", step_percent: 10, max_percent: 100 },
+  },
+  {
+    label: "+50 points per correct answer in a row, up to 500",
+    bonus: { kind: "points", step: 50, max: 500 },
+  },
+];
+
+const streakBonusChoices = createListCollection({
+  items: streakBonuses.map(({ label }, i) => {
+    return { value: String(i), label };
+  }),
+});
+
+const scoringRulesFields: Array<{
+  key: keyof ScoringRules;
+  label: string;
+}> = [
+  { key: "base_score", label: "Score of the first question" },
+  { key: "score_step", label: "Score increase per question" },
+  { key: "max_score", label: "Maximum score" },
+  { key: "slowest_percent", label: "Score kept by the slowest answer (%)" },
+  { key: "fastest_bonus_percent", label: "Bonus of the fastest answer (%)" },
+];
+
+const GameSettingsFields: React.FC<{
+  settings: GameSettings;
+  onChange: (settings: GameSettings) => void;
+}> = ({ settings, onChange }) => {
+  const scoring = settings.scoring;
+
+  return (
+    <>
+      {timingFields.map(({ key, label, choices }) => (
+        <Select.Root
+          key={key}
+          collection={choices}
+          value={[String(settings[key])]}
+          onValueChange={(e) => {
+            onChange({ ...settings, [key]: Number(e.items[0].value) });
+          }}
+        >
+          <Select.Label>{label}</Select.Label>
+          <Select.Trigger>
+            <Select.ValueText />
+          </Select.Trigger>
+          <Select.Content>
+            {choices.items.map((item) => (
+              <Select.Item color="black" item={item} key={item.value}>
+                {item.label}
+              </Select.Item>
+            ))}
+          </Select.Content>
+        </Select.Root>
+      ))}
+
+      <Select.Root
+        collection={scoringPresetChoices}
+        value={[scoring.preset]}
+        onValueChange={(e) => {
+          const preset = e.items[0].value;
+          onChange({
+            ...settings,
+            scoring:
+              preset === ScoringPreset.Custom
+                ? { preset, ...defaultScoringRules }
+                : { preset },
+          });
+        }}
+      >
+        <Select.Label>Scoring</Select.Label>
+        <Select.Trigger>
+          <Select.ValueText />
+        </Select.Trigger>
+        <Select.Content>
+          {scoringPresetChoices.items.map((item) => (
+            <Select.Item color="black" item={item} key={item.value}>
+              {item.label}
+            </Select.Item>
+          ))}
+        </Select.Content>
+      </Select.Root>
+
+      {scoring.preset === ScoringPreset.Custom &&
+        scoringRulesFields.map(({ key, label }) => (
+          <Field.Root key={key}>
+            <Field.Label>{label}</Field.Label>
+            <Input
+              type="number"
+              min={0}
+              step={1}
+              value={scoring[key]}
+              onChange={(e) => {
+                onChange({
+                  ...settings,
+                  scoring: { ...scoring, [key]: Number(e.target.value) },
+                });
+              }}
+            />
+          </Field.Root>
+        ))}
+
+      <Select.Root
+        collection={penaltyChoices}
+        value={[String(settings.wrong_answer_penalty_percent)]}
+        onValueChange={(e) => {
+          onChange({
+            ...settings,
+            wrong_answer_penalty_percent: Number(e.items[0].value),
+          });
+        }}
+      >
+        <Select.Label>Penalty for wrong answers</Select.Label>
+        <Select.Trigger>
+          <Select.ValueText />
+        </Select.Trigger>
+        <Select.Content>
+          {penaltyChoices.items.map((item) => (
+            <Select.Item color="black" item={item} key={item.value}>
+              {item.label}
+            </Select.Item>
+          ))}
+        </Select.Content>
+      </Select.Root>
+
+      <Select.Root
+        collection={streakBonusChoices}
+        value={[
+          String(
+            streakBonuses.findIndex(
+              ({ bonus }) => bonus.kind === settings.streak_bonus.kind,
+            ),
+          ),
+        ]}
+        onValueChange={(e) => {
+          onChange({
+            ...settings,
+            streak_bonus: streakBonuses[Number(e.items[0].value)].bonus,
+          });
+        }}
+      >
+        <Select.Label>Streak bonus</Select.Label>
+        <Select.Trigger>
+          <Select.ValueText />
+        </Select.Trigger>
+        <Select.Content>
+          {streakBonusChoices.items.map((item) => (
+            <Select.Item color="black" item={item} key={item.value}>
+              {item.label}
+            </Select.Item>
+          ))}
+        </Select.Content>
+      </Select.Root>
+    </>
+  );
+};
+
+export { defaultGameSettings };
+export default GameSettingsFields;
@@ -18,7 +18,9 @@ const Scoreboard: React.FC<{
 
           return (
             <List.Item key={user.name}>
-              {user.name}: {user.score} {score !== undefined && `(+${score})`}
+              {user.name}: {user.score}{" "}
+              {score !== undefined && `(${score < 0 ? "" : "+"}${score})`}
+              {user.streak > 1 && ` 🔥${user.streak}`}
             </List.Item>
           );
         })}
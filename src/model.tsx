@@ -1,25 +1,89 @@
-type SpotifyUser = {
-  display_name: string;
-};
 
-type Playlist = {
-  id: string;
-  name: string;
-  owner: SpotifyUser;
+enum SearchKind {
+  Playlist = "playlist",
+  Album = "album",
+  Artist = "artist",
+  Track = "track",
+}
+
+type SearchItem =
+  | { kind: SearchKind.Playlist; id: string; name: string; owner: string }
+  | {
+      kind: SearchKind.Album;
+      id: string;
+      name: string;
+      artists: Array<string>;
+      year: number | null;
+    }
+  | { kind: SearchKind.Artist; id: string; name: string }
+  | {
+      kind: SearchKind.Track;
+      id: string;
+      name: string;
+      album: string;
+      artists: Array<string>;
+    };
+
+enum TimeRange {
+  Short = "short",
+  Medium = "medium",
+  Long = "long",
+}
+
+type Collection =
+  | {
+      kind: SearchKind.Playlist | SearchKind.Album | SearchKind.Artist;
+      id: string;
+    }
+  | { kind: "liked_songs" }
+  | { kind: "top_tracks"; time_range: TimeRange }
+  | { kind: "recently_played" }
+  | { kind: "imported"; id: string };
+
+type GameSource = Collection & {
+  weight: number;
 };
 
 enum QuestionType {
   Song = "Song",
   Artist = "Artist",
   Album = "Album",
+  Year = "Year",
+  Decade = "Decade",
+}
+
+enum Difficulty {
+  Easy = "easy",
+  Normal = "normal",
+  Hard = "hard",
+}
+
+enum ScoringPreset {
+  Flat = "flat",
+  Escalating = "escalating",
+  SpeedHeavy = "speed_heavy",
+  AccuracyOnly = "accuracy_only",
+  Custom = "custom",
+}
+
+enum AnswerMode {
+  MultipleChoice = "multiple_choice",
+  FreeText = "free_text",
 }
 
 type Question = {
   question_type: QuestionType;
+  // empty for free-text questions
   choices: Array<string>;
   score: number;
   bonus: number;
-  song_url: string;
+  answer_mode: AnswerMode;
+};
+
+type QuizPack = {
+  id: string;
+  name: string;
+  num_questions: number;
 };
 
 type User = {
@@ -30,24 +94,61 @@ type User = {
 type UserGameState = {
   name: string;
   score: number;
+  // number of consecutive correct answers
+  streak: number;
+};
+
+// rules of custom scoring
+type ScoringRules = {
+  base_score: number;
+  score_step: number;
+  max_score: number;
+  slowest_percent: number;
+  fastest_bonus_percent: number;
+};
+
+type ScoringPolicy =
+  | { preset: Exclude<ScoringPreset, ScoringPreset.Custom> }
+  | ({ preset: ScoringPreset.Custom } & ScoringRules);
+
+type StreakBonus =
+  | { kind: "none" }
+  | { kind: "multiplier"; step_percent: number; max_percent: number }
+  | { kind: "points"; step: number; max: number };
+
+type GameSettings = {
+  answer_time_ms: number;
+  reveal_time_ms: number;
+  pre_roll_ms: number;
+  scoring: ScoringPolicy;
+  wrong_answer_penalty_percent: number;
+  streak_bonus: StreakBonus;
 };
 
 type PlayingGameState = {
   question: Question;
   question_id: number;
+  // negative while the question's pre-roll is running
   song_progress_ms: number;
+  settings: GameSettings;
   users: Array<UserGameState>;
 };
 
+type Verdict = "correct" | "close" | "wrong";
+
 type UserSubmission = {
   user_name: string;
+  choice: number | null;
+  text: string | null;
   score: number;
+  verdict: Verdict | null;
   submitted_at_ms: number;
 };
 
 type WaitingForNextQuestionState = {
   answer: string;
   correct_submissions: Array<UserSubmission>;
+  submissions: Array<UserSubmission>;
   users: Array<UserGameState>;
 };
 
@@ -57,18 +158,34 @@ type WaitingGameState = {
 
 type EndedGameState = {
   users: Array<UserGameState>;
+  seed: number | null;
 };
 
 export type {
-  Playlist,
+  SearchItem,
+  Collection,
+  GameSource,
+  GameSettings,
+  ScoringPolicy,
+  ScoringRules,
+  StreakBonus,
   User,
   UserGameState,
   Question,
+  QuizPack,
   PlayingGameState,
   WaitingGameState,
   WaitingForNextQuestionState,
   EndedGameState,
   UserSubmission,
+  Verdict,
 };
 
-export { QuestionType };
+export {
+  AnswerMode,
+  Difficulty,
+  QuestionType,
+  ScoringPreset,
+  SearchKind,
+  TimeRange,
+};
@@ -42,6 +42,16 @@ async function put<T>(url: string, body: T): Promise<Response> {
   }
 }
 
+async function del(url: string): Promise<Response> {
+  const response = await fetch(url, { method: "DELETE" });
+  if (response.status !== 200) {
+    const text = await response.text();
+    throw new Error(`Failed to send DELETE request ${url}: ${text}`);
+  } else {
+    return response;
+  }
+}
+
 function getUserData(): User | null {
   const id = localStorage.getItem("userId");
   if (id === null) {
@@ -54,4 +64,4 @@ function getUserData(): User | null {
   return { id, name };
 }
 
-export { get, post, put, getUserData };
+export { get, post, put, del, getUserData };